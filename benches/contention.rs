@@ -0,0 +1,64 @@
+//! Compare the lock-free and locked paths of a `StateMachine` under
+//! contention. Run it with and without `--features parking_lot` to compare the
+//! `std` and `parking_lot` locks:
+//!
+//!     cargo bench --bench contention
+//!     cargo bench --bench contention --features parking_lot
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use circuit_breaker::{CircuitBreaker, StateMachine};
+
+const THREADS: usize = 8;
+const ITERATIONS: usize = 1_000_000;
+
+/// Run `f` on `THREADS` threads sharing one breaker and return the elapsed time.
+fn contend(breaker: &StateMachine, f: fn(&StateMachine)) -> Duration {
+    let start = Instant::now();
+    let handles: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let breaker = breaker.clone();
+            thread::spawn(move || {
+                for _ in 0..ITERATIONS {
+                    f(&breaker);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let breaker = StateMachine::builder().build().unwrap();
+    let lock = if cfg!(feature = "parking_lot") {
+        "parking_lot"
+    } else {
+        "std"
+    };
+    println!(
+        "{} threads x {} calls in the closed state, {} locks",
+        THREADS, ITERATIONS, lock
+    );
+
+    // `current_state` always takes the lock, like `is_call_permitted` did
+    // before it read the state through an atomic.
+    let locked = contend(&breaker, |breaker| {
+        std::hint::black_box(breaker.current_state());
+    });
+    println!("    locked (current_state):          {:?}", locked);
+
+    let lock_free = contend(&breaker, |breaker| {
+        std::hint::black_box(breaker.is_call_permitted());
+    });
+    println!("    lock-free (is_call_permitted):   {:?}", lock_free);
+
+    // Recording an outcome always updates the machine under the lock.
+    let recorded = contend(&breaker, |breaker| {
+        std::hint::black_box(breaker.call(|| Ok::<_, ()>(()))).ok();
+    });
+    println!("    locked (call):                   {:?}", recorded);
+}
@@ -0,0 +1,224 @@
+//! The first version of the circuit breaker, built on a `RwLock` shared by
+//! clones, which used to deadlock when the called function used the breaker.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+enum State {
+    // The circuit breaker is closed and allowing requests
+    // to pass through
+    Closed,
+    // The circuit breaker is open and blocking requests until
+    // the given instant, after which it goes half-open
+    Open(Instant),
+    // The circuit breaker is half-open and allowing a limited
+    // number of requests to pass through
+    HalfOpen,
+}
+
+// Clones share the same state, so a clone can be handed to another thread or
+// into the called closure.
+#[derive(Clone)]
+struct CircuitBreaker {
+    state: Arc<RwLock<State>>,
+    // How long the circuit breaker stays open before going half-open,
+    // checked when a call asks for permission rather than by a timer
+    // thread, which used to be spawned and leaked on every trip
+    trip_timeout: Duration,
+    // The maximum number of requests allowed through in
+    // the closed state
+    max_failures: usize,
+    // The number of consecutive failures in the closed
+    // state
+    consecutive_failures: Arc<AtomicUsize>,
+}
+
+impl CircuitBreaker {
+    pub fn new(max_failures: usize, trip_timeout: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            state: Arc::new(RwLock::new(State::Closed)),
+            max_failures,
+            trip_timeout,
+            consecutive_failures: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn is_call_permitted(&self) -> bool {
+        let state = self.state.read().unwrap();
+        match *state {
+            State::Closed => self.consecutive_failures.load(Ordering::Relaxed) < self.max_failures,
+            State::Open(until) => Instant::now() >= until,
+            State::HalfOpen => true,
+        }
+    }
+
+    pub fn call<F, T, E>(&self, f: F) -> Option<Result<T, E>>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        // Decide under a short-lived lock which is released before calling
+        // `f`, so `f` may call back into the circuit breaker
+        let permitted = {
+            let mut state = self.state.write().unwrap();
+            match *state {
+                // If the circuit breaker is closed, try the request
+                // unless too many requests failed in a row
+                State::Closed => {
+                    if self.consecutive_failures.load(Ordering::Relaxed) < self.max_failures {
+                        true
+                    } else {
+                        *state = self.open();
+                        self.consecutive_failures.store(0, Ordering::Relaxed);
+                        false
+                    }
+                }
+                // If the circuit breaker is open, go half-open once the
+                // trip timeout has expired
+                State::Open(until) => {
+                    if Instant::now() >= until {
+                        *state = State::HalfOpen;
+                        true
+                    } else {
+                        false
+                    }
+                }
+                // If the circuit breaker is half-open, attempt a limited
+                // number of requests to pass through
+                State::HalfOpen => true,
+            }
+        };
+        if !permitted {
+            return None;
+        }
+
+        let result = f();
+        self.record(result.is_ok());
+        Some(result)
+    }
+
+    // Record the result of a request under a fresh lock
+    fn record(&self, success: bool) {
+        let mut state = self.state.write().unwrap();
+        match *state {
+            State::Closed => {
+                if !success {
+                    self.consecutive_failures.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            State::Open(_) => {}
+            State::HalfOpen => {
+                if success {
+                    *state = State::Closed;
+                } else {
+                    *state = self.open();
+                }
+            }
+        }
+    }
+
+    // Return the open state lasting for the trip timeout from now on
+    fn open(&self) -> State {
+        State::Open(Instant::now() + self.trip_timeout)
+    }
+}
+
+fn request(dice: u32) -> Result<u32, String> {
+    if dice > 6 {
+        Err("400: Bad request.".to_string())
+    } else {
+        Ok(dice)
+    }
+}
+
+fn main() {
+    let cb = CircuitBreaker::new(3, Duration::from_secs(10));
+    println!("Circuit Breaker has been set with");
+    println!("    * 3 as maximum consecutive failures");
+    println!("    * 10 seconds as the trip timeout");
+    println!();
+
+    println!("Circuit Breaker is in the initial state, which is closed.");
+    // The circuit breaker is in the closed state, so the function
+    // will be executed
+    let result = cb.call(|| request(5));
+    println!("Result for request_dice(5): {:?}", result);
+
+    // The lock is not held while the function is executed, so it
+    // can ask a cloned handle for permission without deadlocking
+    let handle = cb.clone();
+    let result = cb.call(|| {
+        println!(
+            "Permitted from within the call: {}",
+            handle.is_call_permitted()
+        );
+        request(4)
+    });
+    println!("Result for request_dice(4): {:?}", result);
+
+    println!("Circuit Breaker is encounting 3 errors in a row ...");
+    // The function returns an error 3 times in a row, so the circuit
+    // breaker transitions to the open state
+    cb.call(|| request(10));
+    cb.call(|| request(10));
+    cb.call(|| request(10));
+
+    // The circuit breaker is in the open state, so the function is
+    // not executed
+    let result = cb.call(|| request(2));
+    println!("Result for request_dice(2): {:?}", result);
+
+    // The circuit breaker is in the half-open state after trip_timeout
+    // seconds, so the function will be executed
+    println!("Let's have fun by doing nothing in 20 seconds :)");
+    println!("...");
+    thread::sleep(Duration::from_secs(20));
+    let result = cb.call(|| request(5));
+    println!("Result for request_dice(5): {:?}", result);
+    let result = cb.call(|| request(6));
+    println!("Result for request_dice(6): {:?}", result);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn closure_can_query_a_clone_without_deadlocking() {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let cb = CircuitBreaker::new(3, Duration::from_secs(10));
+            let handle = cb.clone();
+            let result = cb.call(|| {
+                let permitted = handle.is_call_permitted();
+                Ok::<_, ()>(permitted)
+            });
+            tx.send(result).unwrap();
+        });
+
+        let result = rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("the call deadlocked");
+        assert_eq!(result, Some(Ok(true)));
+    }
+
+    #[test]
+    fn goes_half_open_once_the_trip_timeout_expires() {
+        let cb = CircuitBreaker::new(1, Duration::from_millis(20));
+        assert_eq!(
+            cb.call(|| request(10)),
+            Some(Err("400: Bad request.".to_string()))
+        );
+        // The failure is acted upon by the next call, which trips it.
+        assert_eq!(cb.call(|| request(1)), None);
+        assert!(!cb.is_call_permitted());
+
+        thread::sleep(Duration::from_millis(30));
+        assert!(cb.is_call_permitted());
+        assert_eq!(cb.call(|| request(1)), Some(Ok(1)));
+        assert_eq!(cb.call(|| request(2)), Some(Ok(2)));
+    }
+}
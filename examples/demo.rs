@@ -0,0 +1,52 @@
+use std::thread;
+use std::time::Duration;
+
+use circuit_breaker::{CircuitBreaker, StateMachine};
+
+fn request(dice: u32) -> Result<u32, String> {
+    if dice > 6 {
+        Err("400: Bad request.".to_string())
+    } else {
+        Ok(dice)
+    }
+}
+
+#[allow(unused_must_use)]
+fn main() {
+    let circuit_breaker = StateMachine::new(3, Duration::from_secs(10));
+    println!("Circuit Breaker has been set with");
+    println!("    * 3 as maximum consecutive failures");
+    println!("    * 10 seconds as the trip timeout");
+    println!();
+
+    println!("Circuit Breaker is in the initial state, which is closed.");
+    // The circuit breaker is in the closed state, so the function
+    // will be executed
+    let result = circuit_breaker.call(|| request(5));
+    println!("Result for request_dice(5): {:?}", result);
+
+    println!("Circuit Breaker is encounting 3 errors in a row ...");
+    // The function returns an error 3 times in a row, so the circuit
+    // breaker transitions to the open state
+    println!("The first one...");
+    circuit_breaker.call(|| request(10));
+    println!("The second one...");
+    circuit_breaker.call(|| request(10));
+    println!("The third one...");
+    circuit_breaker.call(|| request(10));
+
+    // The circuit breaker is in the open state, so the function is
+    // not executed
+    let result = circuit_breaker.call(|| request(2));
+    println!("Result for request_dice(2): {:?}", result);
+
+    // The circuit breaker is in the half-open state after trip_timeout
+    // seconds, so the function will be executed
+    println!("Let's have fun by doing nothing in 20 seconds :)");
+    println!("...");
+    thread::sleep(Duration::from_secs(20));
+    let result = circuit_breaker.call(|| request(5));
+    println!("Result for request_dice(5): {:?}", result);
+    let result = circuit_breaker.call(|| request(6));
+    println!("Result for request_dice(6): {:?}", result);
+}
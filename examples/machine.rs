@@ -0,0 +1,64 @@
+//! Drive the `no_std` core of the circuit breaker with caller-provided
+//! timestamps, as an embedded device would with its own tick counter.
+//!
+//! Only the parts of the crate available without the `std` feature are used.
+//! Its test builds the crate without `std` and runs the same steps:
+//!
+//!     cargo run --example machine --no-default-features
+//!     cargo test --example machine --no-default-features
+#![no_std]
+
+extern crate std;
+
+use core::time::Duration;
+use std::println;
+
+use circuit_breaker::{Config, Machine, State};
+
+fn main() {
+    run();
+}
+
+// Trip the circuit breaker, let the trip timeout expire and close it again with
+// a successful probe.
+fn run() {
+    let config = Config {
+        max_failures: 3,
+        trip_timeout: Duration::from_secs(10),
+        ..Config::default()
+    };
+    let mut machine = Machine::new(config, Duration::ZERO);
+    // No recovery ramp is configured, so no random sample is ever needed.
+    let sample = || 0.0;
+
+    for tick in 1..=3 {
+        let now = Duration::from_secs(tick);
+        assert!(machine.is_call_permitted(now, sample));
+        machine.on_error(false, 1, now);
+    }
+    println!(
+        "After 3 failures: {:?}",
+        machine.state(Duration::from_secs(3))
+    );
+    assert!(!machine.is_call_permitted(Duration::from_secs(4), sample));
+
+    // The trip timeout has expired 14 seconds in, so a probe is permitted.
+    let now = Duration::from_secs(14);
+    assert!(machine.is_call_permitted(now, sample));
+    assert_eq!(machine.state(now), State::HalfOpen);
+    machine.on_success(false, now);
+    println!("After a successful probe: {:?}", machine.state(now));
+    assert_eq!(machine.state(now), State::Closed);
+
+    for (from, to) in machine.take_transitions() {
+        println!("{:?} -> {:?}", from, to);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn drives_the_core() {
+        super::run();
+    }
+}
@@ -0,0 +1,84 @@
+use core::time::Duration;
+
+/// How long the circuit breaker stays open after tripping.
+///
+/// The first trip waits `trip_timeout`, and every consecutive reopen without
+/// closing in between multiplies the previous delay by `multiplier`, capped at
+/// `max_timeout`. Each delay is then randomized by up to `jitter` times itself
+/// in either direction.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Backoff {
+    pub(crate) trip_timeout: Duration,
+    pub(crate) max_timeout: Option<Duration>,
+    pub(crate) multiplier: f64,
+    pub(crate) jitter: f64,
+}
+
+impl Backoff {
+    /// Return the delay for the given number of consecutive reopens.
+    pub(crate) fn delay(&self, reopens: u32) -> Duration {
+        let factor = powi(self.multiplier, reopens);
+        let delay = Duration::try_from_secs_f64(self.trip_timeout.as_secs_f64() * factor)
+            .unwrap_or(Duration::MAX);
+
+        match self.max_timeout {
+            Some(max_timeout) => delay.min(max_timeout),
+            None => delay,
+        }
+    }
+
+    /// Randomize `delay` within `delay ± jitter * delay`, drawing a number in
+    /// `[0.0, 1.0)` from `sample` if there is any jitter.
+    pub(crate) fn jitter(&self, delay: Duration, sample: impl FnOnce() -> f64) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        let factor = 1.0 + jitter * (2.0 * sample() - 1.0);
+        Duration::try_from_secs_f64(delay.as_secs_f64() * factor).unwrap_or(Duration::MAX)
+    }
+}
+
+// Raise `base` to the power of `exp` by squaring, as `f64::powi` needs `std`.
+pub(crate) fn powi(mut base: f64, mut exp: u32) -> f64 {
+    let mut result = 1.0;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result *= base;
+        }
+        base *= base;
+        exp >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_doubles_up_to_the_cap() {
+        let backoff = Backoff {
+            trip_timeout: Duration::from_secs(1),
+            max_timeout: Some(Duration::from_secs(4)),
+            multiplier: 2.0,
+            jitter: 0.0,
+        };
+        let delays = [0, 1, 2, 3].map(|reopens| backoff.delay(reopens));
+        assert_eq!(delays, [1, 2, 4, 4].map(Duration::from_secs),);
+    }
+
+    #[test]
+    fn jitter_stays_within_its_fraction_of_the_delay() {
+        let backoff = Backoff {
+            trip_timeout: Duration::from_secs(10),
+            max_timeout: None,
+            multiplier: 1.0,
+            jitter: 0.5,
+        };
+        let delay = Duration::from_secs(10);
+        assert_eq!(backoff.jitter(delay, || 0.0), Duration::from_secs(5));
+        assert_eq!(backoff.jitter(delay, || 0.5), delay);
+        assert!(backoff.jitter(delay, || 0.999) < Duration::from_secs(15));
+    }
+}
@@ -0,0 +1,380 @@
+use std::any::Any;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::{self, Clock, InstantClock, MonotonicClock};
+use crate::config::{
+    BackoffOnHalfOpenFailure, BatchFailure, Config, ConfigError, CostBudget, RecoveryRamp,
+    Settling, SlidingWindow,
+};
+use crate::policy::Policy;
+use crate::rng::{Rng, SystemRng};
+use crate::state::State;
+use crate::state_machine::StateMachine;
+
+/// A builder for configuring a `StateMachine`.
+///
+/// Fields which are not set fall back to the defaults:
+///     * 5 as maximum consecutive failures
+///     * no warning before tripping
+///     * 30 seconds as the trip timeout
+///     * 1.0 as the backoff multiplier, so every trip waits the same timeout
+///     * repeating the last trip timeout when a half-open probe fails
+///     * resetting the backoff as soon as the circuit breaker closes
+///     * no jitter of the trip timeout
+///     * 1 as consecutive successes required to close from half-open
+///     * 1 concurrent trial call in the half-open state
+///     * 30 seconds before the slots of probes which never recorded an
+///       outcome are given back
+///     * no sliding window or moving average, so consecutive failures trip the
+///       circuit breaker
+///     * 0.5 as the failure rate threshold once a sliding window is set
+///     * no minimum number of calls before the sliding window's rates are evaluated
+///     * no slow call detection
+///     * 1.0 as the slow call rate threshold once a sliding window is set
+///     * slow half-open probes counting toward closing when they succeed
+///     * no recovery ramp after closing
+///     * no settling after closing
+///     * no cost budget
+///     * no policy replacing the thresholds
+///     * a failed batch counting as one failure
+///     * starting closed
+///     * every error counting as failure
+///     * no name
+///     * the last 16 transitions kept in the history
+///     * the `SystemClock` as the source of time, or the `PerformanceClock` on
+///       `wasm32` with the `wasm` feature
+///     * the `SystemRng` as the source of randomness
+#[derive(Debug)]
+pub struct StateMachineBuilder {
+    pub(crate) config: Config,
+    pub(crate) name: Option<String>,
+    pub(crate) policy: Option<Box<dyn Policy>>,
+    pub(crate) clock: Arc<dyn MonotonicClock>,
+    pub(crate) rng: Arc<dyn Rng>,
+    pub(crate) history_capacity: Option<usize>,
+    // A `TripPredicate<E>` for the `E` it was registered for.
+    pub(crate) trip_predicate: Option<Box<dyn Any + Send + Sync>>,
+}
+
+pub(crate) type TripPredicate<E> = Box<dyn Fn(&E) -> bool + Send + Sync>;
+
+impl Default for StateMachineBuilder {
+    fn default() -> Self {
+        Self::from_config(Config::default())
+    }
+}
+
+impl StateMachineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from the thresholds and timeouts in `config`.
+    pub fn from_config(config: Config) -> Self {
+        StateMachineBuilder {
+            config,
+            name: None,
+            policy: None,
+            clock: clock::default_clock(),
+            rng: Arc::new(SystemRng::new()),
+            history_capacity: None,
+            trip_predicate: None,
+        }
+    }
+
+    /// Set the number of consecutive failures which trips the circuit breaker.
+    pub fn max_failures(mut self, max_failures: usize) -> Self {
+        self.config.max_failures = max_failures;
+        self
+    }
+
+    /// Raise a warning, as passed to `set_on_warn`, once consecutive failures
+    /// reach `warn_threshold` without tripping the circuit breaker yet. It
+    /// must be below `max_failures`.
+    pub fn warn_threshold(mut self, warn_threshold: usize) -> Self {
+        self.config.warn_threshold = Some(warn_threshold);
+        self
+    }
+
+    /// Set how long the circuit breaker stays open before going half-open.
+    /// With `Duration::ZERO`, the next call after tripping is let through as
+    /// a probe.
+    pub fn trip_timeout(mut self, trip_timeout: Duration) -> Self {
+        self.config.trip_timeout = trip_timeout;
+        self
+    }
+
+    /// Set the upper bound of the trip timeout when backing off.
+    pub fn max_timeout(mut self, max_timeout: Duration) -> Self {
+        self.config.max_timeout = Some(max_timeout);
+        self
+    }
+
+    /// Set the factor applied to the trip timeout every time the circuit breaker
+    /// reopens without closing in between.
+    pub fn backoff_multiplier(mut self, backoff_multiplier: f64) -> Self {
+        self.config.backoff_multiplier = backoff_multiplier;
+        self
+    }
+
+    /// Keep the backoff after closing and step it down by one trip every
+    /// `successes` consecutive successes in the closed state instead, so that
+    /// it only fully resets after sustained health.
+    pub fn backoff_decay_successes(mut self, successes: usize) -> Self {
+        self.config.backoff_decay_successes = Some(successes);
+        self
+    }
+
+    /// Set whether a failed probe in the half-open state advances the backoff
+    /// or waits the same timeout again.
+    pub fn backoff_on_half_open_failure(
+        mut self,
+        backoff_on_half_open_failure: BackoffOnHalfOpenFailure,
+    ) -> Self {
+        self.config.backoff_on_half_open_failure = backoff_on_half_open_failure;
+        self
+    }
+
+    /// Randomize every trip timeout by up to `jitter`, between 0.0 and 1.0,
+    /// times itself in either direction, drawing from the configured `Rng`.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.config.jitter = jitter;
+        self
+    }
+
+    /// Set the number of consecutive successes in the half-open state which
+    /// closes the circuit breaker again.
+    pub fn success_threshold(mut self, success_threshold: usize) -> Self {
+        self.config.success_threshold = success_threshold;
+        self
+    }
+
+    /// Set the number of concurrent trial calls permitted in the half-open
+    /// state, beyond which calls are rejected until a probe's outcome is
+    /// recorded.
+    pub fn half_open_max_calls(mut self, half_open_max_calls: usize) -> Self {
+        self.config.half_open_max_calls = Some(half_open_max_calls);
+        self
+    }
+
+    /// Set how long after the last probe slot was taken in the half-open state
+    /// the slots of probes which never recorded an outcome are given back.
+    pub fn probe_timeout(mut self, probe_timeout: Duration) -> Self {
+        self.config.probe_timeout = probe_timeout;
+        self
+    }
+
+    /// Trip on the failure rate over the last `window_size` calls instead of
+    /// on consecutive failures.
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        self.config.window = Some(SlidingWindow::Count(window_size));
+        self
+    }
+
+    /// Trip on the failure rate over the calls within the last
+    /// `window_duration` instead of on consecutive failures.
+    ///
+    /// The window is split into ten buckets which expire as time advances.
+    pub fn window_duration(mut self, window_duration: Duration) -> Self {
+        self.config.window = Some(SlidingWindow::Time(window_duration));
+        self
+    }
+
+    /// Trip on an exponentially weighted moving average of the failure rate,
+    /// giving the latest outcome a weight of `alpha`, instead of on consecutive
+    /// failures or on a sliding window.
+    ///
+    /// It needs no buffer of outcomes. A slow call counts as a failure.
+    pub fn ewma(mut self, alpha: f64) -> Self {
+        self.config.ewma_alpha = Some(alpha);
+        self
+    }
+
+    /// Set the failure rate, between 0.0 and 1.0, which trips the circuit
+    /// breaker when reached within the sliding window or by the moving
+    /// average.
+    pub fn failure_rate_threshold(mut self, failure_rate_threshold: f64) -> Self {
+        self.config.failure_rate_threshold = failure_rate_threshold;
+        self
+    }
+
+    /// Keep the circuit breaker closed until the sliding window holds at least
+    /// `minimum_throughput` calls, or as many calls were averaged since it
+    /// closed, whatever their failure rate.
+    pub fn minimum_throughput(mut self, minimum_throughput: usize) -> Self {
+        self.config.minimum_throughput = minimum_throughput;
+        self
+    }
+
+    /// Treat calls taking longer than `slow_call_threshold` as unhealthy for
+    /// tripping purposes, even if they succeed.
+    pub fn slow_call_threshold(mut self, slow_call_threshold: Duration) -> Self {
+        self.config.slow_call_threshold = Some(slow_call_threshold);
+        self
+    }
+
+    /// Count a half-open probe which succeeds slower than the
+    /// `slow_call_threshold` as a failed probe, reopening the circuit breaker,
+    /// rather than toward closing it.
+    pub fn fail_slow_probes(mut self, fail_slow_probes: bool) -> Self {
+        self.config.fail_slow_probes = fail_slow_probes;
+        self
+    }
+
+    /// Set the slow call rate, between 0.0 and 1.0, which trips the circuit
+    /// breaker when reached within the sliding window.
+    pub fn slow_call_rate_threshold(mut self, slow_call_rate_threshold: f64) -> Self {
+        self.config.slow_call_rate_threshold = slow_call_rate_threshold;
+        self
+    }
+
+    /// After closing, admit calls for `duration` with a probability growing
+    /// linearly from `start_fraction` to 1.0.
+    pub fn recovery_ramp(mut self, duration: Duration, start_fraction: f64) -> Self {
+        self.config.recovery_ramp = Some(RecoveryRamp {
+            duration,
+            start_fraction,
+        });
+        self
+    }
+
+    /// After closing, require `multiplier` times `max_failures` consecutive
+    /// failures to trip the circuit breaker again for `duration`, so that it
+    /// does not flap on the first failures after recovering.
+    pub fn settling(mut self, duration: Duration, multiplier: usize) -> Self {
+        self.config.settling = Some(Settling {
+            duration,
+            multiplier,
+        });
+        self
+    }
+
+    /// Trip the circuit breaker when the cost of recent failures, i.e. the
+    /// weights given by `call_weighted`, exceeds `budget`, draining the cost
+    /// so that a full budget is forgotten after `drain_time`.
+    ///
+    /// It replaces the consecutive failures and the sliding window's rates.
+    pub fn cost_budget(mut self, budget: usize, drain_time: Duration) -> Self {
+        self.config.cost_budget = Some(CostBudget { budget, drain_time });
+        self
+    }
+
+    /// Count failures for `warmup` after the circuit breaker is built without
+    /// ever tripping it, e.g. while dependencies are still starting up.
+    pub fn warmup(mut self, warmup: Duration) -> Self {
+        self.config.warmup = Some(warmup);
+        self
+    }
+
+    /// Trip the circuit breaker when `policy` tells so, e.g. an `AnyOf`
+    /// combining several conditions, rather than on the thresholds.
+    pub fn policy(mut self, policy: impl Policy + 'static) -> Self {
+        self.policy = Some(Box::new(policy));
+        self
+    }
+
+    /// Set whether a failed batch of `call_batch` counts as one failure or as a
+    /// failure per operation.
+    pub fn batch_failure(mut self, batch_failure: BatchFailure) -> Self {
+        self.config.batch_failure = batch_failure;
+        self
+    }
+
+    /// Start in `initial_state` instead of closed, e.g. open for a dependency
+    /// known to be down at boot. An open state must have time `remaining`.
+    pub fn initial_state(mut self, initial_state: State) -> Self {
+        self.config.initial_state = initial_state;
+        self
+    }
+
+    /// Count an error of type `E` as failure only if `predicate` returns `true`
+    /// for it, e.g. to trip on server errors but not on a not-found error.
+    ///
+    /// The predicate applies to every call and to `Permit::record_error`. An
+    /// error counts as failure only if both the predicate and the classifier
+    /// of the call, e.g. the one given to `call_with`, count it.
+    pub fn trip_only_on<E: 'static>(
+        mut self,
+        predicate: impl Fn(&E) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        let predicate: TripPredicate<E> = Box::new(predicate);
+        self.trip_predicate = Some(Box::new(predicate));
+        self
+    }
+
+    /// Count no error of type `E` as failure, so that only timeouts and
+    /// panics trip the circuit breaker, e.g. with `call_timeout`.
+    pub fn count_timeouts_only<E: 'static>(self) -> Self {
+        self.trip_only_on(|_: &E| false)
+    }
+
+    /// Name the circuit breaker, e.g. after the dependency it protects, for
+    /// logging and metrics.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Keep the last `capacity` transitions in the history returned by
+    /// `transition_history`.
+    pub fn history_capacity(mut self, capacity: usize) -> Self {
+        self.history_capacity = Some(capacity);
+        self
+    }
+
+    /// Set the source of time, e.g. a `TestClock` which can be advanced manually.
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(InstantClock::new(clock));
+        self
+    }
+
+    /// Set the source of time as the time elapsed since an arbitrary epoch,
+    /// e.g. on targets where `Instant` is unavailable.
+    pub fn monotonic_clock(mut self, clock: impl MonotonicClock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Set the source of randomness, e.g. a `SeededRng` for deterministic tests.
+    /// The same seed and calls then yield the same jitter and ramp admissions.
+    pub fn rng(mut self, rng: impl Rng + 'static) -> Self {
+        self.rng = Arc::new(rng);
+        self
+    }
+
+    /// Build the `StateMachine`, checking the thresholds first.
+    ///
+    /// Return:
+    ///     `Err(error)` if `Config::validate` rejects the thresholds.
+    ///     `Ok(breaker)` otherwise.
+    pub fn build(self) -> Result<StateMachine, ConfigError> {
+        self.config.validate()?;
+        Ok(StateMachine::from_builder(self))
+    }
+
+    /// Build the `StateMachine` without checking the thresholds.
+    pub fn build_unchecked(self) -> StateMachine {
+        StateMachine::from_builder(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit_breaker::CircuitBreaker;
+    use crate::state::State;
+
+    #[test]
+    fn build_rejects_an_invalid_config() {
+        let result = StateMachineBuilder::new().half_open_max_calls(0).build();
+        assert_eq!(result.err(), Some(ConfigError::ZeroHalfOpenMaxCalls));
+    }
+
+    #[test]
+    fn build_unchecked_skips_validation() {
+        let breaker = StateMachineBuilder::new().max_failures(0).build_unchecked();
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+}
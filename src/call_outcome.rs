@@ -0,0 +1,37 @@
+use core::time::Duration;
+
+use crate::state::State;
+
+/// The outcome of `StateMachine::call_detailed`, telling apart the reasons a
+/// call was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallOutcome<T, E> {
+    /// The call succeeded.
+    Success(T),
+    /// The call failed, which was recorded as failure.
+    Failure(E),
+    /// The call was rejected as the circuit breaker is open.
+    ///
+    /// `retry_after` is the remaining trip timeout, or `Duration::MAX` if the
+    /// circuit breaker was isolated and only closes when reset.
+    RejectedOpen { retry_after: Duration },
+    /// The call was rejected as the circuit breaker is half-open and all its
+    /// probes are taken.
+    RejectedHalfOpenBusy,
+    /// The call was shed by the recovery ramp, which admits only some calls
+    /// for a while after the circuit breaker closed.
+    RejectedThrottled,
+}
+
+/// The states around a call of `StateMachine::call_traced`, e.g. to annotate a
+/// tracing span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceInfo {
+    /// The state when the call asked for permission.
+    pub before: State,
+    /// Whether the call was permitted as a half-open probe.
+    pub probe: bool,
+    /// The state once the outcome of the call was recorded, or once it was
+    /// rejected.
+    pub after: State,
+}
@@ -0,0 +1,208 @@
+use crate::error::Error;
+
+pub trait CircuitBreaker {
+    /// Ask permission to call.
+    ///
+    /// Return:
+    ///     `true` if a call is allowed.
+    ///     `false` if a call is prohibited.
+    fn is_call_permitted(&self) -> bool;
+
+    /// Call a given function within Circuit Breaker.
+    ///
+    /// Depending on the excution result, the call will be recorded as success or failure.
+    fn call<F, T, E>(&self, f: F) -> Result<T, Error<E>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        T: 'static,
+        E: 'static,
+    {
+        self.call_with(|_| true, f)
+    }
+
+    /// Call a given mutable function within Circuit Breaker, recording the
+    /// outcome the same way as `call`.
+    ///
+    /// Unlike `call`, the function is borrowed, so one closure capturing mutable
+    /// state can be called again, e.g. by a retry loop.
+    fn call_mut<F, T, E>(&self, f: &mut F) -> Result<T, Error<E>>
+    where
+        F: FnMut() -> Result<T, E>,
+        T: 'static,
+        E: 'static,
+    {
+        self.call(f)
+    }
+
+    /// Call a given function within Circuit Breaker, recording an error as
+    /// failure only if `classify` returns `true` for it.
+    ///
+    /// Errors classified as non-failures, e.g. a validation error which is the
+    /// caller's fault, are still returned but recorded as success.
+    fn call_with<P, F, T, E>(&self, classify: P, f: F) -> Result<T, Error<E>>
+    where
+        P: Fn(&E) -> bool,
+        F: FnOnce() -> Result<T, E>,
+        T: 'static,
+        E: 'static,
+    {
+        self.call_weighted(|err| usize::from(classify(err)), f)
+    }
+
+    /// Call a given function within Circuit Breaker, counting an error as
+    /// `weigh(&err)` consecutive failures.
+    ///
+    /// A weight of `0` records the error as success, like a non-failure in
+    /// `call_with`. A sliding window still records a weighted error as one
+    /// failed call.
+    fn call_weighted<W, F, T, E>(&self, weigh: W, f: F) -> Result<T, Error<E>>
+    where
+        W: Fn(&E) -> usize,
+        F: FnOnce() -> Result<T, E>,
+        T: 'static,
+        E: 'static;
+
+    /// Call a given function within Circuit Breaker, keeping the inner error
+    /// apart from a rejection.
+    ///
+    /// Return:
+    ///     `Some(result)` with the result of `f` if a call is allowed.
+    ///     `None` if a call is prohibited, in which case `f` is not called.
+    fn try_call<F, T, E>(&self, f: F) -> Option<Result<T, E>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        T: 'static,
+        E: 'static,
+    {
+        match self.call(f) {
+            Ok(ok) => Some(Ok(ok)),
+            Err(Error::Inner(err)) => Some(Err(err)),
+            Err(Error::Rejected { .. } | Error::Timeout | Error::Panic) => None,
+        }
+    }
+
+    /// Call a given function within Circuit Breaker, falling back to
+    /// `fallback` when the call is rejected or fails.
+    ///
+    /// The fallback receives `None` if the call was rejected and the inner
+    /// error otherwise. The call is recorded the same way as with `call`.
+    fn call_with_fallback<F, G, T, E>(&self, f: F, fallback: G) -> Result<T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+        G: FnOnce(Option<&E>) -> Result<T, E>,
+        T: 'static,
+        E: 'static,
+    {
+        match self.call(f) {
+            Ok(ok) => Ok(ok),
+            Err(Error::Inner(err)) => fallback(Some(&err)),
+            Err(Error::Rejected { .. } | Error::Timeout | Error::Panic) => fallback(None),
+        }
+    }
+
+    /// Call `f` within this circuit breaker and, if it is rejected or fails,
+    /// `g` within `other`, e.g. a secondary dependency with its own circuit
+    /// breaker.
+    ///
+    /// Return:
+    ///     the result of `f` if it succeeds.
+    ///     the result of `g` within `other` otherwise, including its rejection.
+    fn or_else<B, F, G, T, E>(&self, other: &B, f: F, g: G) -> Result<T, Error<E>>
+    where
+        B: CircuitBreaker,
+        F: FnOnce() -> Result<T, E>,
+        G: FnOnce() -> Result<T, E>,
+        T: 'static,
+        E: 'static,
+    {
+        match self.call(f) {
+            Ok(ok) => Ok(ok),
+            Err(_) => other.call(g),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::state::State;
+    use crate::state_machine::StateMachine;
+
+    #[test]
+    fn fallback_runs_on_inner_error_and_rejection() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        let fallback = |err: Option<&&str>| match err {
+            Some(err) => Ok(format!("failed: {err}")),
+            None => Ok("rejected".to_string()),
+        };
+        assert_eq!(
+            breaker.call_with_fallback(|| Ok("fresh".to_string()), fallback),
+            Ok("fresh".to_string())
+        );
+        assert_eq!(
+            breaker.call_with_fallback(|| Err("500"), fallback),
+            Ok("failed: 500".to_string())
+        );
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+        assert_eq!(
+            breaker.call_with_fallback(|| Ok("fresh".to_string()), fallback),
+            Ok("rejected".to_string())
+        );
+    }
+
+    #[test]
+    fn try_call_keeps_the_inner_error_apart_from_rejection() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        assert_eq!(breaker.try_call(|| Ok::<_, &str>(1)), Some(Ok(1)));
+        assert_eq!(breaker.try_call(|| Err::<u32, _>("500")), Some(Err("500")));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+
+        let mut called = false;
+        let result = breaker.try_call(|| {
+            called = true;
+            Ok::<_, &str>(1)
+        });
+        assert_eq!(result, None);
+        assert!(!called);
+    }
+
+    #[test]
+    fn call_mut_reuses_one_stateful_closure() {
+        let breaker = StateMachine::new(3, Duration::from_secs(60));
+        let mut attempts = 0;
+        let mut attempt = || {
+            attempts += 1;
+            if attempts < 3 {
+                Err(attempts)
+            } else {
+                Ok(attempts)
+            }
+        };
+        let mut result = breaker.call_mut(&mut attempt);
+        while matches!(result, Err(Error::Inner(_))) {
+            result = breaker.call_mut(&mut attempt);
+        }
+
+        assert!(matches!(result, Ok(3)));
+        assert_eq!(attempts, 3);
+        assert_eq!(breaker.metrics().failed_calls, 2);
+        assert_eq!(breaker.metrics().successful_calls, 1);
+    }
+
+    #[test]
+    fn or_else_fails_over_to_the_secondary() {
+        let primary = StateMachine::new(1, Duration::from_secs(60));
+        let secondary = StateMachine::new(1, Duration::from_secs(60));
+        primary.trip();
+        let result = primary.or_else(&secondary, || Ok::<_, &str>("primary"), || Ok("secondary"));
+        assert!(matches!(result, Ok("secondary")));
+
+        primary.reset();
+        let result = primary.or_else(&secondary, || Err("refused"), || Ok("secondary"));
+        assert!(matches!(result, Ok("secondary")));
+        assert!(primary.is_open());
+        assert!(secondary.is_closed());
+    }
+}
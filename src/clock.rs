@@ -0,0 +1,195 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[cfg(any(test, feature = "test-util"))]
+use crate::sync::Mutex;
+
+/// A source of the current time for a `StateMachine`.
+pub trait Clock: Send + Sync {
+    /// Return the current instant.
+    fn now(&self) -> Instant;
+}
+
+impl fmt::Debug for dyn Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Clock")
+    }
+}
+
+/// A source of the current time for a `StateMachine` as the time elapsed since
+/// an arbitrary epoch, for targets where `Instant` is unavailable, e.g.
+/// `wasm32-unknown-unknown`.
+///
+/// The time must never go backwards. Every `Clock` is wrapped into one.
+pub trait MonotonicClock: Send + Sync {
+    /// Return the time elapsed since the epoch.
+    fn now(&self) -> Duration;
+}
+
+impl fmt::Debug for dyn MonotonicClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MonotonicClock")
+    }
+}
+
+/// A `MonotonicClock` reading a `Clock` relative to its instant when wrapped.
+pub(crate) struct InstantClock<C> {
+    clock: C,
+    epoch: Instant,
+}
+
+impl<C: Clock> InstantClock<C> {
+    pub(crate) fn new(clock: C) -> Self {
+        let epoch = clock.now();
+        InstantClock { clock, epoch }
+    }
+}
+
+impl<C: Clock> MonotonicClock for InstantClock<C> {
+    fn now(&self) -> Duration {
+        self.clock.now().saturating_duration_since(self.epoch)
+    }
+}
+
+/// A `MonotonicClock` reading `performance.now()`, the default on `wasm32`
+/// with the `wasm` feature enabled.
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PerformanceClock;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+#[wasm_bindgen::prelude::wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+impl MonotonicClock for PerformanceClock {
+    fn now(&self) -> Duration {
+        // Rounded to whole microseconds, which is as precise as browsers get.
+        Duration::from_micros((performance_now() * 1000.0) as u64)
+    }
+}
+
+/// Return the clock a `StateMachine` uses unless given another one.
+pub(crate) fn default_clock() -> Arc<dyn MonotonicClock> {
+    #[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+    return Arc::new(PerformanceClock);
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+    return Arc::new(InstantClock::new(SystemClock));
+}
+
+/// A `Clock` reading the system's monotonic clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A `Clock` which only moves when it is advanced manually.
+///
+/// Clones share the same time, so a clone can be handed to a `StateMachine`
+/// while the original is kept to advance it.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Clone)]
+pub struct TestClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl TestClock {
+    pub fn new() -> Self {
+        TestClock {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Move the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock() += duration;
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        *self.now.lock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+    use crate::circuit_breaker::CircuitBreaker;
+    use crate::state_machine::StateMachine;
+
+    #[test]
+    fn test_clock_clones_share_the_time() {
+        let clock = TestClock::new();
+        let start = clock.now();
+        let handle = clock.clone();
+        handle.advance(Duration::from_secs(20));
+        assert_eq!(clock.now() - start, Duration::from_secs(20));
+    }
+
+    #[test]
+    fn goes_half_open_on_an_advanced_test_clock() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(20))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert!(!breaker.is_call_permitted());
+
+        clock.advance(Duration::from_secs(20));
+        assert!(breaker.is_call_permitted());
+        assert_eq!(breaker.current_state(), crate::State::HalfOpen);
+    }
+
+    // A clock counting milliseconds without ever reading an `Instant`, like
+    // one backed by `performance.now()`.
+    #[derive(Clone, Default)]
+    struct Millis(Arc<AtomicU64>);
+
+    impl MonotonicClock for Millis {
+        fn now(&self) -> Duration {
+            Duration::from_millis(self.0.load(Ordering::Relaxed))
+        }
+    }
+
+    #[test]
+    fn goes_half_open_on_a_monotonic_clock() {
+        let clock = Millis::default();
+        clock.0.store(5_000, Ordering::Relaxed);
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(10))
+            .monotonic_clock(clock.clone())
+            .build()
+            .unwrap();
+        breaker.trip();
+
+        clock.0.fetch_add(9_999, Ordering::Relaxed);
+        assert!(!breaker.is_call_permitted());
+        clock.0.fetch_add(1, Ordering::Relaxed);
+        assert!(breaker.is_call_permitted());
+        assert!(breaker.is_half_open());
+    }
+}
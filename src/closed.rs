@@ -0,0 +1,71 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::state_machine::StateMachine;
+
+/// A future resolving once the circuit breaker is closed, returned by
+/// `StateMachine::closed`.
+#[must_use = "futures do nothing unless polled"]
+pub struct Closed<'a> {
+    breaker: &'a StateMachine,
+}
+
+impl<'a> Closed<'a> {
+    pub(crate) fn new(breaker: &'a StateMachine) -> Self {
+        Closed { breaker }
+    }
+}
+
+impl Future for Closed<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.breaker.register_closed_waker(cx.waker()) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+    use std::time::Duration;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn resolves_once_reset() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        breaker.trip();
+        let woken = Arc::new(Flag::default());
+        let waker = Waker::from(woken.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut closed = Box::pin(breaker.closed());
+        assert!(closed.as_mut().poll(&mut cx).is_pending());
+
+        breaker.reset();
+        assert!(woken.0.load(Ordering::Relaxed));
+        assert!(closed.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn resolves_at_once_when_closed() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(Box::pin(breaker.closed()).as_mut().poll(&mut cx).is_ready());
+    }
+}
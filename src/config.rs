@@ -0,0 +1,634 @@
+use core::error;
+use core::fmt;
+use core::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::backoff::Backoff;
+use crate::state::State;
+
+const DEFAULT_MAX_FAILURES: usize = 5;
+const DEFAULT_TRIP_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_SUCCESS_THRESHOLD: usize = 1;
+const DEFAULT_HALF_OPEN_MAX_CALLS: usize = 1;
+const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_BACKOFF_MULTIPLIER: f64 = 1.0;
+const DEFAULT_FAILURE_RATE_THRESHOLD: f64 = 0.5;
+const DEFAULT_SLOW_CALL_RATE_THRESHOLD: f64 = 1.0;
+
+/// The thresholds and timeouts of a `StateMachine`.
+///
+/// With the `serde` feature enabled it can be deserialized, e.g. from a
+/// configuration file, where durations are given in milliseconds and missing
+/// fields fall back to their defaults.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Config {
+    /// The number of consecutive failures which trips the circuit breaker.
+    pub max_failures: usize,
+    /// The number of consecutive failures, below `max_failures`, at which a
+    /// warning is raised without changing the state.
+    pub warn_threshold: Option<usize>,
+    /// How long the circuit breaker stays open before going half-open. With
+    /// zero, the next call after tripping is let through as a probe.
+    #[cfg_attr(feature = "serde", serde(with = "duration_ms"))]
+    pub trip_timeout: Duration,
+    /// The upper bound of the trip timeout when backing off.
+    #[cfg_attr(feature = "serde", serde(with = "duration_ms::option"))]
+    pub max_timeout: Option<Duration>,
+    /// The factor applied to the trip timeout every time the circuit breaker
+    /// reopens without closing in between.
+    pub backoff_multiplier: f64,
+    /// Whether a failed probe in the half-open state advances the backoff or
+    /// waits the same timeout again.
+    pub backoff_on_half_open_failure: BackoffOnHalfOpenFailure,
+    /// The number of consecutive successes in the closed state which step the
+    /// backoff down by one trip, rather than resetting it as soon as the
+    /// circuit breaker closes.
+    pub backoff_decay_successes: Option<usize>,
+    /// The fraction, between 0.0 and 1.0, of the trip timeout by which it is
+    /// randomized in either direction, so that circuit breakers which opened
+    /// together do not go half-open together.
+    pub jitter: f64,
+    /// The number of consecutive successes in the half-open state which closes
+    /// the circuit breaker again.
+    pub success_threshold: usize,
+    /// The number of concurrent trial calls permitted in the half-open state,
+    /// beyond which calls are rejected, or `None` to permit all calls.
+    pub half_open_max_calls: Option<usize>,
+    /// How long after the last probe slot was taken the slots of probes which
+    /// never recorded an outcome are given back, e.g. for permissions only
+    /// asked with `is_call_permitted`, so that they cannot keep the circuit
+    /// breaker half-open forever.
+    #[cfg_attr(feature = "serde", serde(with = "duration_ms"))]
+    pub probe_timeout: Duration,
+    /// The sliding window to evaluate the failure rate over instead of
+    /// counting consecutive failures.
+    pub window: Option<SlidingWindow>,
+    /// The smoothing factor, between 0.0 exclusive and 1.0, of an
+    /// exponentially weighted moving average of the failure rate to trip on
+    /// instead of the consecutive failures or the sliding window.
+    pub ewma_alpha: Option<f64>,
+    /// The failure rate, between 0.0 and 1.0, which trips the circuit breaker
+    /// when reached within the sliding window or by the moving average.
+    pub failure_rate_threshold: f64,
+    /// The number of calls the sliding window must hold before its rates can
+    /// trip the circuit breaker.
+    pub minimum_throughput: usize,
+    /// The duration above which a call is treated as unhealthy even if it succeeds.
+    #[cfg_attr(feature = "serde", serde(with = "duration_ms::option"))]
+    pub slow_call_threshold: Option<Duration>,
+    /// Whether a half-open probe which succeeds slower than the
+    /// `slow_call_threshold` counts as a failed probe rather than toward
+    /// closing the circuit breaker.
+    pub fail_slow_probes: bool,
+    /// The slow call rate, between 0.0 and 1.0, which trips the circuit breaker
+    /// when reached within the sliding window.
+    pub slow_call_rate_threshold: f64,
+    /// The period after closing during which only a growing fraction of calls
+    /// is admitted.
+    pub recovery_ramp: Option<RecoveryRamp>,
+    /// The period after closing during which more consecutive failures are
+    /// needed to trip the circuit breaker again, to avoid flapping.
+    pub settling: Option<Settling>,
+    /// The leaky bucket of failure costs which trips the circuit breaker when
+    /// it overflows, instead of the consecutive failures or the rates.
+    pub cost_budget: Option<CostBudget>,
+    /// The period after creation during which failures are counted but never
+    /// trip the circuit breaker, e.g. while dependencies start up.
+    #[cfg_attr(feature = "serde", serde(with = "duration_ms::option"))]
+    pub warmup: Option<Duration>,
+    /// How a failed batch of `call_batch` is counted.
+    pub batch_failure: BatchFailure,
+    /// The state the circuit breaker starts in, e.g. open for a dependency
+    /// known to be down at boot, which stays open for its `remaining` time.
+    pub initial_state: State,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_failures: DEFAULT_MAX_FAILURES,
+            warn_threshold: None,
+            trip_timeout: DEFAULT_TRIP_TIMEOUT,
+            max_timeout: None,
+            backoff_multiplier: DEFAULT_BACKOFF_MULTIPLIER,
+            backoff_on_half_open_failure: BackoffOnHalfOpenFailure::default(),
+            backoff_decay_successes: None,
+            jitter: 0.0,
+            success_threshold: DEFAULT_SUCCESS_THRESHOLD,
+            half_open_max_calls: Some(DEFAULT_HALF_OPEN_MAX_CALLS),
+            probe_timeout: DEFAULT_PROBE_TIMEOUT,
+            window: None,
+            ewma_alpha: None,
+            failure_rate_threshold: DEFAULT_FAILURE_RATE_THRESHOLD,
+            minimum_throughput: 0,
+            slow_call_threshold: None,
+            fail_slow_probes: false,
+            slow_call_rate_threshold: DEFAULT_SLOW_CALL_RATE_THRESHOLD,
+            recovery_ramp: None,
+            settling: None,
+            cost_budget: None,
+            warmup: None,
+            batch_failure: BatchFailure::default(),
+            initial_state: State::Closed,
+        }
+    }
+}
+
+impl Config {
+    /// Check that the thresholds can trip the circuit breaker sensibly and
+    /// that it cannot get stuck open or half-open.
+    ///
+    /// Return:
+    ///     `Err(error)` if `max_failures`, the window size or
+    ///     `half_open_max_calls` is 0, if the backoff multiplier is negative or
+    ///     not finite, if a rate, a fraction or `ewma_alpha` is out of range,
+    ///     if `warn_threshold` is not below `max_failures`, if the settling
+    ///     multiplier is 0, or if the initial state is open without any time
+    ///     remaining.
+    ///     `Ok(())` otherwise.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_failures == 0 {
+            return Err(ConfigError::ZeroMaxFailures);
+        }
+        if let Some(threshold) = self
+            .warn_threshold
+            .filter(|&threshold| threshold >= self.max_failures)
+        {
+            return Err(ConfigError::WarnThresholdNotBelowMaxFailures(threshold));
+        }
+        if !(0.0..=1.0).contains(&self.failure_rate_threshold) {
+            return Err(ConfigError::FailureRateThresholdOutOfRange(
+                self.failure_rate_threshold,
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.slow_call_rate_threshold) {
+            return Err(ConfigError::SlowCallRateThresholdOutOfRange(
+                self.slow_call_rate_threshold,
+            ));
+        }
+        if self.window == Some(SlidingWindow::Count(0)) {
+            return Err(ConfigError::ZeroWindowSize);
+        }
+        if self.half_open_max_calls == Some(0) {
+            return Err(ConfigError::ZeroHalfOpenMaxCalls);
+        }
+        if !(self.backoff_multiplier.is_finite() && self.backoff_multiplier >= 0.0) {
+            return Err(ConfigError::BackoffMultiplierOutOfRange(
+                self.backoff_multiplier,
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err(ConfigError::JitterOutOfRange(self.jitter));
+        }
+        if let Some(ramp) = self.recovery_ramp {
+            if !(0.0..=1.0).contains(&ramp.start_fraction) {
+                return Err(ConfigError::StartFractionOutOfRange(ramp.start_fraction));
+            }
+        }
+        if self
+            .settling
+            .is_some_and(|settling| settling.multiplier == 0)
+        {
+            return Err(ConfigError::ZeroSettlingMultiplier);
+        }
+        if let State::Open {
+            remaining: Duration::ZERO,
+            ..
+        } = self.initial_state
+        {
+            return Err(ConfigError::ZeroInitialTimeout);
+        }
+        if let Some(alpha) = self.ewma_alpha {
+            if !(alpha > 0.0 && alpha <= 1.0) {
+                return Err(ConfigError::EwmaAlphaOutOfRange(alpha));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn backoff(&self) -> Backoff {
+        Backoff {
+            trip_timeout: self.trip_timeout,
+            max_timeout: self.max_timeout,
+            multiplier: self.backoff_multiplier,
+            jitter: self.jitter,
+        }
+    }
+}
+
+/// Why a `Config` was rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigError {
+    /// `max_failures` is 0, so the circuit breaker would trip without a failure.
+    ZeroMaxFailures,
+    /// `failure_rate_threshold` is not between 0.0 and 1.0.
+    FailureRateThresholdOutOfRange(f64),
+    /// The count-based sliding window holds no calls.
+    ZeroWindowSize,
+    /// `ewma_alpha` is not above 0.0 and at most 1.0.
+    EwmaAlphaOutOfRange(f64),
+    /// `half_open_max_calls` is 0, so the circuit breaker would stay half-open
+    /// without ever letting a probe through.
+    ZeroHalfOpenMaxCalls,
+    /// `backoff_multiplier` is negative or not finite, so the circuit breaker
+    /// would stay open forever.
+    BackoffMultiplierOutOfRange(f64),
+    /// `jitter` is not between 0.0 and 1.0.
+    JitterOutOfRange(f64),
+    /// `slow_call_rate_threshold` is not between 0.0 and 1.0.
+    SlowCallRateThresholdOutOfRange(f64),
+    /// The `start_fraction` of the recovery ramp is not between 0.0 and 1.0.
+    StartFractionOutOfRange(f64),
+    /// The initial state is open without any time remaining.
+    ZeroInitialTimeout,
+    /// The settling multiplies `max_failures` by zero.
+    ZeroSettlingMultiplier,
+    /// `warn_threshold` is not below `max_failures`, so the circuit breaker
+    /// would trip before warning.
+    WarnThresholdNotBelowMaxFailures(usize),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::ZeroMaxFailures => f.write_str("max_failures must be at least 1"),
+            ConfigError::FailureRateThresholdOutOfRange(threshold) => write!(
+                f,
+                "failure_rate_threshold must be between 0.0 and 1.0, got {}",
+                threshold
+            ),
+            ConfigError::ZeroWindowSize => f.write_str("window size must be at least 1"),
+            ConfigError::EwmaAlphaOutOfRange(alpha) => write!(
+                f,
+                "ewma_alpha must be above 0.0 and at most 1.0, got {}",
+                alpha
+            ),
+            ConfigError::ZeroHalfOpenMaxCalls => {
+                f.write_str("half_open_max_calls must be at least 1")
+            }
+            ConfigError::BackoffMultiplierOutOfRange(multiplier) => write!(
+                f,
+                "backoff_multiplier must be finite and at least 0.0, got {}",
+                multiplier
+            ),
+            ConfigError::JitterOutOfRange(jitter) => {
+                write!(f, "jitter must be between 0.0 and 1.0, got {}", jitter)
+            }
+            ConfigError::SlowCallRateThresholdOutOfRange(threshold) => write!(
+                f,
+                "slow_call_rate_threshold must be between 0.0 and 1.0, got {}",
+                threshold
+            ),
+            ConfigError::StartFractionOutOfRange(fraction) => write!(
+                f,
+                "recovery ramp start_fraction must be between 0.0 and 1.0, got {}",
+                fraction
+            ),
+            ConfigError::ZeroInitialTimeout => {
+                f.write_str("an open initial state must have time remaining")
+            }
+            ConfigError::ZeroSettlingMultiplier => {
+                f.write_str("settling multiplier must be at least 1")
+            }
+            ConfigError::WarnThresholdNotBelowMaxFailures(threshold) => write!(
+                f,
+                "warn_threshold must be below max_failures, got {}",
+                threshold
+            ),
+        }
+    }
+}
+
+impl error::Error for ConfigError {}
+
+/// How the trip timeout evolves when a probe fails in the half-open state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum BackoffOnHalfOpenFailure {
+    /// Wait for the next step of the backoff, i.e. the last timeout times the
+    /// backoff multiplier.
+    Escalate,
+    /// Wait the same timeout as the last time the circuit breaker opened.
+    #[default]
+    Repeat,
+}
+
+/// How a failed batch of operations is counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum BatchFailure {
+    /// Count the whole batch as one failure.
+    #[default]
+    Once,
+    /// Count every operation of the batch as a failure.
+    PerItem,
+}
+
+/// A sliding window of calls to evaluate the failure rate over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SlidingWindow {
+    /// The last `n` calls.
+    Count(usize),
+    /// The calls within the last `duration`, split into ten buckets which
+    /// expire as time advances.
+    Time(#[cfg_attr(feature = "serde", serde(with = "duration_ms"))] Duration),
+}
+
+/// A period after closing during which only a growing fraction of calls is
+/// admitted, to avoid slamming a recovered dependency with full traffic.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecoveryRamp {
+    /// How long the ramp lasts after closing.
+    #[cfg_attr(feature = "serde", serde(with = "duration_ms"))]
+    pub duration: Duration,
+    /// The fraction of calls admitted right after closing.
+    pub start_fraction: f64,
+}
+
+impl RecoveryRamp {
+    /// Return the fraction of calls to admit `elapsed` after closing, growing
+    /// linearly from `start_fraction` to 1.0 over the ramp's duration.
+    pub(crate) fn admit_fraction(&self, elapsed: Duration) -> f64 {
+        if elapsed >= self.duration {
+            return 1.0;
+        }
+        let progress = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+        self.start_fraction + (1.0 - self.start_fraction) * progress
+    }
+}
+
+/// A leaky bucket of failure costs, e.g. the weights given by `call_weighted`,
+/// which trips the circuit breaker when it overflows.
+///
+/// The bucket drains linearly, so a sustained cost of `budget` per
+/// `drain_time` never trips it while a burst above `budget` does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CostBudget {
+    /// The total cost the bucket holds before tripping the circuit breaker.
+    pub budget: usize,
+    /// How long a full bucket takes to drain.
+    #[cfg_attr(feature = "serde", serde(with = "duration_ms"))]
+    pub drain_time: Duration,
+}
+
+/// A period after closing during which `max_failures` is multiplied, so that a
+/// recovered dependency is not cut off again by the first few failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Settling {
+    /// How long the settling lasts after closing.
+    #[cfg_attr(feature = "serde", serde(with = "duration_ms"))]
+    pub duration: Duration,
+    /// The factor `max_failures` is multiplied by while settling.
+    pub multiplier: usize,
+}
+
+/// (De)serialize a `Duration` as a number of milliseconds.
+#[cfg(feature = "serde")]
+pub(crate) mod duration_ms {
+    use core::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+
+    pub(crate) mod option {
+        use core::time::Duration;
+
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub(crate) fn serialize<S: Serializer>(
+            duration: &Option<Duration>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match duration {
+                Some(duration) => serializer.serialize_some(&(duration.as_millis() as u64)),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Duration>, D::Error> {
+            Option::<u64>::deserialize(deserializer).map(|millis| millis.map(Duration::from_millis))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(config: Config) -> Result<(), ConfigError> {
+        config.validate()
+    }
+
+    #[test]
+    fn accepts_the_defaults() {
+        assert_eq!(validate(Config::default()), Ok(()));
+    }
+
+    #[test]
+    fn rejects_zero_max_failures() {
+        let config = Config {
+            max_failures: 0,
+            ..Config::default()
+        };
+        assert_eq!(validate(config), Err(ConfigError::ZeroMaxFailures));
+    }
+
+    #[test]
+    fn rejects_a_warn_threshold_not_below_max_failures() {
+        for warn_threshold in [5, 6] {
+            let config = Config {
+                max_failures: 5,
+                warn_threshold: Some(warn_threshold),
+                ..Config::default()
+            };
+            assert_eq!(
+                validate(config),
+                Err(ConfigError::WarnThresholdNotBelowMaxFailures(
+                    warn_threshold
+                ))
+            );
+        }
+        let config = Config {
+            max_failures: 5,
+            warn_threshold: Some(4),
+            ..Config::default()
+        };
+        assert_eq!(validate(config), Ok(()));
+    }
+
+    #[test]
+    fn rejects_a_failure_rate_threshold_out_of_range() {
+        for threshold in [-0.1, 1.5, f64::NAN] {
+            let config = Config {
+                failure_rate_threshold: threshold,
+                ..Config::default()
+            };
+            assert!(matches!(
+                validate(config),
+                Err(ConfigError::FailureRateThresholdOutOfRange(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_a_zero_window_size() {
+        let config = Config {
+            window: Some(SlidingWindow::Count(0)),
+            ..Config::default()
+        };
+        assert_eq!(validate(config), Err(ConfigError::ZeroWindowSize));
+    }
+
+    #[test]
+    fn rejects_zero_half_open_max_calls() {
+        let config = Config {
+            half_open_max_calls: Some(0),
+            ..Config::default()
+        };
+        assert_eq!(validate(config), Err(ConfigError::ZeroHalfOpenMaxCalls));
+    }
+
+    #[test]
+    fn rejects_a_negative_or_non_finite_backoff_multiplier() {
+        for multiplier in [-2.0, f64::NAN, f64::INFINITY] {
+            let config = Config {
+                backoff_multiplier: multiplier,
+                ..Config::default()
+            };
+            assert!(matches!(
+                validate(config),
+                Err(ConfigError::BackoffMultiplierOutOfRange(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_jitter_out_of_range() {
+        for jitter in [-0.5, 1.5, f64::NAN] {
+            let config = Config {
+                jitter,
+                ..Config::default()
+            };
+            assert!(matches!(
+                validate(config),
+                Err(ConfigError::JitterOutOfRange(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_a_slow_call_rate_threshold_out_of_range() {
+        let config = Config {
+            slow_call_rate_threshold: 2.0,
+            ..Config::default()
+        };
+        assert_eq!(
+            validate(config),
+            Err(ConfigError::SlowCallRateThresholdOutOfRange(2.0))
+        );
+    }
+
+    #[test]
+    fn rejects_a_ramp_start_fraction_out_of_range() {
+        let config = Config {
+            recovery_ramp: Some(RecoveryRamp {
+                duration: Duration::from_secs(10),
+                start_fraction: -0.1,
+            }),
+            ..Config::default()
+        };
+        assert_eq!(
+            validate(config),
+            Err(ConfigError::StartFractionOutOfRange(-0.1))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserializes_a_working_breaker_from_json() {
+        use crate::{CircuitBreaker, State, StateMachine};
+
+        let json = r#"{
+            "max_failures": 2,
+            "trip_timeout": 1500,
+            "window": { "count": 10 }
+        }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.max_failures, 2);
+        assert_eq!(config.trip_timeout, Duration::from_millis(1500));
+        assert_eq!(config.window, Some(SlidingWindow::Count(10)));
+        assert_eq!(config.success_threshold, DEFAULT_SUCCESS_THRESHOLD);
+        let round_trip: Config =
+            serde_json::from_str(&serde_json::to_string(&config).unwrap()).unwrap();
+        assert_eq!(round_trip, config);
+
+        let breaker = StateMachine::from_config(config).unwrap();
+        for _ in 0..2 {
+            let _ = breaker.call(|| Err::<(), _>(()));
+        }
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn rejects_ewma_alpha_out_of_range() {
+        for alpha in [0.0, 1.5] {
+            let config = Config {
+                ewma_alpha: Some(alpha),
+                ..Config::default()
+            };
+            assert_eq!(
+                validate(config),
+                Err(ConfigError::EwmaAlphaOutOfRange(alpha))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_an_open_initial_state_without_time_remaining() {
+        let config = Config {
+            initial_state: State::Open {
+                remaining: Duration::ZERO,
+                reason: crate::state::TripReason::Manual,
+            },
+            ..Config::default()
+        };
+        assert_eq!(validate(config), Err(ConfigError::ZeroInitialTimeout));
+    }
+
+    #[test]
+    fn rejects_a_zero_settling_multiplier() {
+        let config = Config {
+            settling: Some(Settling {
+                duration: Duration::from_secs(60),
+                multiplier: 0,
+            }),
+            ..Config::default()
+        };
+        assert_eq!(validate(config), Err(ConfigError::ZeroSettlingMultiplier));
+    }
+}
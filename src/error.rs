@@ -0,0 +1,124 @@
+use core::error;
+use core::fmt;
+use core::time::Duration;
+
+use crate::state::StateLabel;
+
+/// A `CircuitBreaker`'s error.
+#[derive(Debug)]
+pub enum Error<E> {
+    /// An error from inner call.
+    Inner(E),
+    /// An error when call was rejected, in the `state` the circuit breaker was
+    /// in, which may permit calls again after `retry_after` if it is open.
+    Rejected {
+        state: StateLabel,
+        retry_after: Option<Duration>,
+    },
+    /// An error when call did not complete in time.
+    Timeout,
+    /// An error when call panicked.
+    Panic,
+}
+
+impl<E> Error<E> {
+    /// Return the error from the inner call, if it is one.
+    pub fn inner(&self) -> Option<&E> {
+        match self {
+            Error::Inner(err) => Some(err),
+            Error::Rejected { .. } | Error::Timeout | Error::Panic => None,
+        }
+    }
+
+    /// Return the error from the inner call, if it is one, consuming `self`.
+    pub fn into_inner(self) -> Option<E> {
+        match self {
+            Error::Inner(err) => Some(err),
+            Error::Rejected { .. } | Error::Timeout | Error::Panic => None,
+        }
+    }
+
+    /// Tell whether the call was rejected by the circuit breaker.
+    pub fn is_rejected(&self) -> bool {
+        matches!(self, Error::Rejected { .. })
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Inner(err) => err.fmt(f),
+            Error::Rejected { .. } => f.write_str("call rejected by circuit breaker"),
+            Error::Timeout => f.write_str("call timed out"),
+            Error::Panic => f.write_str("call panicked"),
+        }
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for Error<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Inner(err) => Some(err),
+            Error::Rejected { .. } | Error::Timeout | Error::Panic => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::string::ToString;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Refused;
+
+    impl fmt::Display for Refused {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("connection refused")
+        }
+    }
+
+    impl error::Error for Refused {}
+
+    #[test]
+    fn boxes_as_a_standard_error() {
+        let err: Box<dyn error::Error> = Box::new(Error::Inner(Refused));
+        assert_eq!(err.to_string(), "connection refused");
+        assert!(err.source().unwrap().is::<Refused>());
+
+        let err: Box<dyn error::Error> = Box::new(Error::<Refused>::Rejected {
+            state: StateLabel::Open,
+            retry_after: None,
+        });
+        assert_eq!(err.to_string(), "call rejected by circuit breaker");
+        assert!(err.source().is_none());
+    }
+
+    fn rejected() -> Error<&'static str> {
+        Error::Rejected {
+            state: StateLabel::Open,
+            retry_after: Some(Duration::from_secs(1)),
+        }
+    }
+
+    #[test]
+    fn inner_returns_the_inner_error_only() {
+        assert_eq!(Error::Inner("500").inner(), Some(&"500"));
+        assert_eq!(rejected().inner(), None);
+    }
+
+    #[test]
+    fn into_inner_returns_the_inner_error_only() {
+        assert_eq!(Error::Inner("500").into_inner(), Some("500"));
+        assert_eq!(rejected().into_inner(), None);
+    }
+
+    #[test]
+    fn is_rejected_tells_rejections_apart() {
+        assert!(!Error::Inner("500").is_rejected());
+        assert!(rejected().is_rejected());
+        assert!(!Error::<&str>::Timeout.is_rejected());
+    }
+}
@@ -0,0 +1,82 @@
+//! Recording HTTP responses, where server errors trip the circuit breaker while
+//! client errors do not, without depending on a specific HTTP crate.
+
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::state_machine::StateMachine;
+
+/// Tell whether a response with the status code `status` counts as failure,
+/// i.e. whether it is a server error.
+pub fn is_failure(status: u16) -> bool {
+    status >= 500
+}
+
+impl StateMachine {
+    /// Record a call which got a response with the status code `status` after
+    /// `elapsed`, as failure for a 5xx server error and as success otherwise,
+    /// including a 4xx client error.
+    ///
+    /// Like with `record_success`, the call must have been permitted by
+    /// `is_call_permitted`.
+    pub fn record_status(&self, status: u16, elapsed: Duration) {
+        if is_failure(status) {
+            self.on_error(elapsed, 1);
+        } else {
+            self.on_success(elapsed);
+        }
+    }
+
+    /// Call a given function sending an HTTP request within Circuit Breaker,
+    /// recording its response by the status code `status` returns for it.
+    ///
+    /// A response with a 5xx status code is recorded as failure but still
+    /// returned, while an error, e.g. a refused connection, is recorded as
+    /// failure and returned as `Error::Inner`.
+    pub fn call_http<F, S, T, E>(&self, status: S, f: F) -> Result<T, Error<E>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        S: FnOnce(&T) -> u16,
+        T: 'static,
+        E: 'static,
+    {
+        self.run(
+            || f().map_err(Error::Inner),
+            |result| match result {
+                Ok(response) => usize::from(is_failure(status(response))),
+                Err(_) => 1,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_status_trips_on_server_errors_only() {
+        let breaker = StateMachine::new(2, Duration::from_secs(60));
+        for status in [200, 404, 503] {
+            breaker.record_status(status, Duration::ZERO);
+        }
+        let metrics = breaker.metrics();
+        assert_eq!(metrics.successful_calls, 2);
+        assert_eq!(metrics.failed_calls, 1);
+        assert!(breaker.is_closed());
+
+        breaker.record_status(503, Duration::ZERO);
+        assert!(breaker.is_open());
+    }
+
+    #[test]
+    fn call_http_returns_server_errors_as_responses() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        let send = |status: u16| breaker.call_http(|&status| status, || Ok::<_, ()>(status));
+        assert!(matches!(send(200), Ok(200)));
+        assert!(matches!(send(404), Ok(404)));
+        assert!(breaker.is_closed());
+        assert!(matches!(send(503), Ok(503)));
+        assert!(breaker.is_open());
+    }
+}
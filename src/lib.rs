@@ -0,0 +1,96 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+mod backoff;
+#[cfg(feature = "std")]
+mod builder;
+mod call_outcome;
+mod circuit_breaker;
+#[cfg(feature = "std")]
+mod clock;
+#[cfg(feature = "async")]
+mod closed;
+mod config;
+mod error;
+#[cfg(feature = "http")]
+pub mod http;
+mod machine;
+mod macros;
+#[cfg(feature = "std")]
+mod metrics;
+#[cfg(feature = "std")]
+mod permit;
+pub mod policy;
+#[cfg(feature = "std")]
+mod registry;
+#[cfg(feature = "std")]
+mod retry;
+#[cfg(feature = "std")]
+mod rng;
+mod split_mix;
+mod state;
+#[cfg(feature = "std")]
+mod state_machine;
+#[cfg(feature = "std")]
+mod sync;
+#[cfg(feature = "tower")]
+pub mod tower;
+mod window;
+
+#[cfg(feature = "std")]
+pub use crate::builder::StateMachineBuilder;
+pub use crate::call_outcome::{CallOutcome, TraceInfo};
+pub use crate::circuit_breaker::CircuitBreaker;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub use crate::clock::PerformanceClock;
+#[cfg(feature = "test-util")]
+pub use crate::clock::TestClock;
+#[cfg(feature = "std")]
+pub use crate::clock::{Clock, MonotonicClock, SystemClock};
+#[cfg(feature = "async")]
+pub use crate::closed::Closed;
+pub use crate::config::{
+    BackoffOnHalfOpenFailure, BatchFailure, Config, ConfigError, CostBudget, RecoveryRamp,
+    Settling, SlidingWindow,
+};
+pub use crate::error::Error;
+pub use crate::machine::Machine;
+#[cfg(feature = "std")]
+pub use crate::metrics::Metrics;
+#[cfg(feature = "std")]
+pub use crate::permit::Permit;
+pub use crate::policy::Policy;
+#[cfg(feature = "std")]
+pub use crate::registry::CircuitBreakerRegistry;
+#[cfg(feature = "std")]
+pub use crate::retry::RetryingBreaker;
+#[cfg(feature = "test-util")]
+pub use crate::rng::SeededRng;
+#[cfg(feature = "std")]
+pub use crate::rng::{Rng, SystemRng};
+#[cfg(feature = "std")]
+pub use crate::state::{BreakerEvent, StateChange};
+pub use crate::state::{DwellTimes, State, StateLabel, StateSnapshot, TransitionEvent, TripReason};
+#[cfg(feature = "std")]
+pub use crate::state_machine::StateMachine;
+pub use crate::window::{Outcome, WindowStats};
+
+// Fail to compile if a handle meant to be shared across threads, e.g. from a
+// static, stops being `Send` and `Sync`, e.g. through a callback stored
+// without those bounds.
+const fn assert_send_sync<T: Send + Sync>() {}
+const _: () = assert_send_sync::<Machine>();
+#[cfg(feature = "std")]
+const _: () = {
+    assert_send_sync::<StateMachine>();
+    assert_send_sync::<StateMachineBuilder>();
+    assert_send_sync::<CircuitBreakerRegistry>();
+    assert_send_sync::<RetryingBreaker>();
+    assert_send_sync::<Permit<'static>>();
+};
+#[cfg(feature = "tower")]
+const _: () = {
+    assert_send_sync::<tower::CircuitBreakerLayer>();
+    assert_send_sync::<tower::CircuitBreaker<()>>();
+};
@@ -0,0 +1,992 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::time::Duration;
+
+use crate::backoff::powi;
+use crate::config::{BackoffOnHalfOpenFailure, Config, CostBudget};
+use crate::policy::Policy;
+use crate::split_mix::{self, GAMMA};
+use crate::state::{DwellTimes, State, StateSnapshot, TransitionEvent, TripReason};
+use crate::window::{Outcome, Window, WindowStats};
+
+// Computes the trip timeout from the number of reopens since last closed.
+struct TimeoutFn(Box<dyn Fn(u32) -> Duration + Send + Sync>);
+
+impl fmt::Debug for TimeoutFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TimeoutFn")
+    }
+}
+
+// Draws the number in `[0.0, 1.0)` jittering a trip timeout.
+struct JitterSource(Box<dyn Fn() -> f64 + Send + Sync>);
+
+impl fmt::Debug for JitterSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("JitterSource")
+    }
+}
+
+/// The number of transitions kept in the history unless set otherwise.
+const DEFAULT_HISTORY_CAPACITY: usize = 16;
+
+#[derive(Debug)]
+enum Status {
+    // The circuit breaker is closed and allowing requests to pass through.
+    Closed,
+    // The circuit breaker is open and blocking requests until the trip duration expired.
+    Open(Duration, TripReason),
+    // The circuit breaker is half-open after waiting for the trip duration and
+    // will allow requests to pass through.
+    HalfOpen,
+    // The circuit breaker is isolated and blocking requests until it is reset,
+    // whatever time has elapsed.
+    ForcedOpen,
+}
+
+impl Status {
+    // Restore a snapshot taken at `now`, an open state staying open for its
+    // remaining trip timeout from then on.
+    fn restore(state: State, now: Duration) -> Self {
+        match state {
+            State::Closed => Status::Closed,
+            State::Open { remaining, reason } => {
+                Status::Open(now.saturating_add(remaining), reason)
+            }
+            State::HalfOpen => Status::HalfOpen,
+            State::ForcedOpen => Status::ForcedOpen,
+        }
+    }
+
+    fn snapshot(&self, now: Duration) -> State {
+        match *self {
+            Status::Closed => State::Closed,
+            Status::Open(until, reason) => State::Open {
+                remaining: until.saturating_sub(now),
+                reason,
+            },
+            Status::HalfOpen => State::HalfOpen,
+            Status::ForcedOpen => State::ForcedOpen,
+        }
+    }
+}
+
+/// The state and thresholds of a circuit breaker without any locking or clock,
+/// usable without `std`.
+///
+/// Every method taking `now` expects the time elapsed since an arbitrary epoch,
+/// e.g. the boot of the device, which must not go backwards. A `StateMachine`
+/// wraps a `Machine` with a lock and a `Clock`.
+#[derive(Debug)]
+pub struct Machine {
+    config: Config,
+    state: Status,
+    consecutive_failures: usize,
+    consecutive_successes: usize,
+    // The number of consecutive successes in the closed state since the
+    // backoff last stepped down.
+    closed_successes: usize,
+    half_open_calls: usize,
+    // When a probe slot was last taken.
+    probe_acquired_at: Duration,
+    // The number of times the circuit breaker opened since it was last closed,
+    // or with a decaying backoff, the steps the backoff has yet to decay.
+    reopens: u32,
+    // When the circuit breaker last closed, if it did.
+    closed_at: Option<Duration>,
+    // When the circuit breaker entered its current state.
+    last_transition: Duration,
+    // The time spent in each state until it was last left.
+    dwell_times: DwellTimes,
+    // When the machine was created, which the warmup is counted from.
+    created_at: Duration,
+    // The moving average of the failure rate and the number of calls averaged
+    // since the circuit breaker last closed.
+    ewma: f64,
+    ewma_calls: usize,
+    // The cost of recent failures in the bucket of the cost budget, and when
+    // it last drained.
+    cost: f64,
+    cost_drained_at: Duration,
+    // The outcomes of recent calls in the closed state when tripping on the
+    // failure rate rather than on consecutive failures.
+    window: Option<Window>,
+    // The transitions which happened since they were last taken.
+    transitions: Vec<(State, State)>,
+    // The most recent transitions, oldest first, bounded by `history_capacity`.
+    history: VecDeque<TransitionEvent>,
+    history_capacity: usize,
+    // The number of transitions made so far.
+    generation: u64,
+    // The state of the generator jittering the trip timeout without a jitter
+    // source.
+    seed: u64,
+    // The source of the jitter of the trip timeout replacing the generator,
+    // if set.
+    jitter_source: Option<JitterSource>,
+    // The condition replacing the built-in thresholds, if set.
+    policy: Option<Box<dyn Policy>>,
+    // The schedule replacing the backoff, if set.
+    timeout_fn: Option<TimeoutFn>,
+    // The message of the last failure since the circuit breaker last closed.
+    last_error: Option<String>,
+    // The consecutive failures which reached the warn threshold since the
+    // warning was last taken.
+    warning: Option<usize>,
+}
+
+impl Machine {
+    pub fn new(config: Config, now: Duration) -> Self {
+        Machine {
+            state: Status::restore(config.initial_state, now),
+            consecutive_failures: 0,
+            consecutive_successes: 0,
+            closed_successes: 0,
+            half_open_calls: 0,
+            probe_acquired_at: now,
+            reopens: 0,
+            closed_at: None,
+            last_transition: now,
+            dwell_times: DwellTimes::default(),
+            created_at: now,
+            ewma: 0.0,
+            ewma_calls: 0,
+            cost: 0.0,
+            cost_drained_at: now,
+            window: config.window.map(|kind| Window::new(kind, now)),
+            transitions: Vec::new(),
+            history: VecDeque::with_capacity(DEFAULT_HISTORY_CAPACITY),
+            history_capacity: DEFAULT_HISTORY_CAPACITY,
+            generation: 0,
+            seed: 0,
+            jitter_source: None,
+            policy: None,
+            timeout_fn: None,
+            last_error: None,
+            warning: None,
+            config,
+        }
+    }
+
+    /// Seed the generator jittering the trip timeout. Machines with the same
+    /// seed and configuration jitter alike.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Jitter the trip timeout by a number in `[0.0, 1.0)` drawn from `f` on
+    /// every trip, e.g. from a random number generator, rather than from the
+    /// generator seeded with `set_seed`.
+    pub fn set_jitter_source(&mut self, f: impl Fn() -> f64 + Send + Sync + 'static) {
+        self.jitter_source = Some(JitterSource(Box::new(f)));
+    }
+
+    /// Open the circuit breaker for the duration `f` returns when given the
+    /// number of times it reopened since it last closed, 0 on the first trip,
+    /// rather than for the backoff of `trip_timeout`. The jitter still applies.
+    pub fn set_timeout_fn(&mut self, f: impl Fn(u32) -> Duration + Send + Sync + 'static) {
+        self.timeout_fn = Some(TimeoutFn(Box::new(f)));
+    }
+
+    /// Keep the last `capacity` transitions in the history, 16 by default,
+    /// dropping the oldest ones beyond it.
+    pub fn set_history_capacity(&mut self, capacity: usize) {
+        self.history_capacity = capacity;
+        while self.history.len() > capacity {
+            self.history.pop_front();
+        }
+    }
+
+    /// Trip the circuit breaker when `policy` tells so rather than on the
+    /// configured `max_failures` or rate thresholds.
+    pub fn set_policy(&mut self, policy: Box<dyn Policy>) {
+        self.policy = Some(policy);
+    }
+
+    /// Return the thresholds and timeouts of the circuit breaker.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Swap the thresholds and timeouts for `config`, keeping the current state
+    /// and counters.
+    ///
+    /// Consecutive failures beyond the new `max_failures` are clamped to it. An
+    /// open circuit breaker keeps its current trip timeout. The sliding window
+    /// is only rebuilt, losing its outcomes, if its kind or size changes.
+    pub fn reconfigure(&mut self, config: Config, now: Duration) {
+        if config.window != self.config.window {
+            self.window = config.window.map(|kind| Window::new(kind, now));
+        }
+        self.consecutive_failures = self.consecutive_failures.min(config.max_failures);
+        self.config = config;
+    }
+
+    /// Tell whether a call which took `elapsed` is slow.
+    pub fn is_slow(&self, elapsed: Duration) -> bool {
+        self.config
+            .slow_call_threshold
+            .is_some_and(|threshold| elapsed > threshold)
+    }
+
+    /// Ask permission to call.
+    ///
+    /// In the half-open state a permitted call takes one of the probe slots
+    /// limited by `half_open_max_calls`, which is given back once the outcome
+    /// of the call is recorded, or once `probe_timeout` elapsed since a slot
+    /// was last taken, as the outcome of an abandoned call never is. During a
+    /// recovery ramp after closing, a call is only permitted if `sample`
+    /// returns a number in `[0.0, 1.0)` below the ramp's current fraction.
+    pub fn is_call_permitted(&mut self, now: Duration, sample: impl FnOnce() -> f64) -> bool {
+        match self.state {
+            Status::Closed => match (self.config.recovery_ramp, self.closed_at) {
+                (Some(ramp), Some(closed_at)) => {
+                    sample() < ramp.admit_fraction(now.saturating_sub(closed_at))
+                }
+                _ => true,
+            },
+            Status::ForcedOpen => false,
+            Status::HalfOpen => self.acquire_probe(now),
+            Status::Open(until, _) => {
+                if now >= until {
+                    self.transit_to_half_open(now);
+                    self.acquire_probe(now)
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    /// Tell whether `is_call_permitted` would permit a call, without taking a
+    /// probe slot or going half-open.
+    ///
+    /// During a recovery ramp, a call is considered permitted whatever the
+    /// ramp's current fraction.
+    pub fn would_permit(&self, now: Duration) -> bool {
+        match self.state {
+            Status::Closed => true,
+            Status::ForcedOpen => false,
+            Status::HalfOpen => self.has_probe_slot(now),
+            Status::Open(until, _) => now >= until,
+        }
+    }
+
+    /// Tell whether `is_call_permitted` would permit a call as a half-open
+    /// probe, without taking a probe slot or going half-open.
+    pub fn would_probe(&self, now: Duration) -> bool {
+        match self.state {
+            Status::Closed | Status::ForcedOpen => false,
+            Status::HalfOpen | Status::Open(..) => self.would_permit(now),
+        }
+    }
+
+    /// Record a permitted call as success.
+    ///
+    /// A slow half-open probe is recorded as failure instead with
+    /// `fail_slow_probes`.
+    pub fn on_success(&mut self, slow: bool, now: Duration) {
+        self.record_success(slow, 1, now);
+    }
+
+    /// Record a permitted call as failure counting as `weight` consecutive
+    /// failures.
+    pub fn on_error(&mut self, slow: bool, weight: usize, now: Duration) {
+        self.record_failure(slow, weight, 1, now);
+    }
+
+    /// Record a permitted batch as `n` calls with the same outcome at once,
+    /// like as many `on_success` or `on_error` calls but in constant time
+    /// whatever `n`, except for filling a count-based sliding window.
+    ///
+    /// The batch gives back one probe slot in the half-open state, where its
+    /// successes count toward closing the circuit breaker together and any
+    /// failure reopens it.
+    pub fn on_batch(&mut self, success: bool, slow: bool, n: usize, now: Duration) {
+        if success {
+            self.record_success(slow, n, now);
+        } else {
+            self.record_failure(slow, 1, n, now);
+        }
+    }
+
+    /// Keep `message` as the last error, e.g. along with `on_error`, until the
+    /// circuit breaker closes.
+    pub fn set_last_error(&mut self, message: String) {
+        self.last_error = Some(message);
+    }
+
+    /// Give back a permission which was never used for a call.
+    pub fn release(&mut self) {
+        if let Status::HalfOpen = self.state {
+            self.release_probe();
+        }
+    }
+
+    /// Force the circuit breaker open for the current trip timeout, regardless
+    /// of its current state, unless it is isolated, which it stays.
+    pub fn trip(&mut self, now: Duration) {
+        if let Status::ForcedOpen = self.state {
+            return;
+        }
+        self.transit_to_open(TripReason::Manual, now);
+    }
+
+    /// Isolate the circuit breaker, blocking all calls until it is reset.
+    pub fn isolate(&mut self, now: Duration) {
+        self.set_state(Status::ForcedOpen, now);
+        self.consecutive_successes = 0;
+        self.half_open_calls = 0;
+    }
+
+    /// Force the circuit breaker closed and clear its counters, regardless of
+    /// its current state. This also resets a decaying backoff.
+    pub fn reset(&mut self, now: Duration) {
+        self.transit_to_closed(now);
+        self.reopens = 0;
+    }
+
+    /// Move an open circuit breaker to half-open before its trip timeout
+    /// expires, e.g. because a health check passed.
+    pub fn half_open(&mut self, now: Duration) {
+        if let Status::Open(..) = self.state {
+            self.transit_to_half_open(now);
+        }
+    }
+
+    /// Make the transitions due by `now` without asking permission to call,
+    /// i.e. go half-open once the trip timeout has expired.
+    ///
+    /// Return:
+    ///     `true` if the state changed.
+    ///     `false` otherwise.
+    pub fn tick(&mut self, now: Duration) -> bool {
+        match self.state {
+            Status::Open(until, _) if now >= until => {
+                self.transit_to_half_open(now);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Return a snapshot of the current state.
+    pub fn state(&self, now: Duration) -> State {
+        self.state.snapshot(now)
+    }
+
+    /// Return how long the circuit breaker has been in its current state.
+    pub fn time_in_current_state(&self, now: Duration) -> Duration {
+        now.saturating_sub(self.last_transition)
+    }
+
+    /// Return the number of consecutive failures recorded in the closed state
+    /// since the last success or since the circuit breaker last closed.
+    pub fn consecutive_failures(&self) -> usize {
+        self.consecutive_failures
+    }
+
+    /// Return the message of the last failure kept with `set_last_error` since
+    /// the circuit breaker last closed.
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    /// Return how long the circuit breaker stays open.
+    ///
+    /// Return:
+    ///     `Some(remaining)` if the circuit breaker is open.
+    ///     `None` otherwise.
+    pub fn retry_after(&self, now: Duration) -> Option<Duration> {
+        match self.state {
+            Status::Open(until, _) => Some(until.saturating_sub(now)),
+            _ => None,
+        }
+    }
+
+    /// Tell why the circuit breaker opened.
+    ///
+    /// Return:
+    ///     `Some(reason)` if the circuit breaker is open.
+    ///     `None` otherwise.
+    pub fn trip_reason(&self) -> Option<TripReason> {
+        match self.state {
+            Status::Open(_, reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// Return the moving average of the failure rate since the circuit breaker
+    /// last closed, or `None` without `ewma_alpha`.
+    pub fn ewma_failure_rate(&self) -> Option<f64> {
+        self.config.ewma_alpha.map(|_| self.ewma)
+    }
+
+    /// Return the outcomes buffered by a count-based sliding window, oldest
+    /// first, e.g. to see why the circuit breaker tripped.
+    ///
+    /// There are none without a sliding window or with a time-based one,
+    /// which only keeps aggregates, returned by `recent_buckets`.
+    pub fn recent_outcomes(&self) -> Vec<Outcome> {
+        self.window.as_ref().map_or_else(Vec::new, Window::outcomes)
+    }
+
+    /// Return the start and the aggregated outcomes of each bucket of a
+    /// time-based sliding window holding any, oldest first.
+    ///
+    /// There are none without a sliding window or with a count-based one,
+    /// whose outcomes `recent_outcomes` returns.
+    pub fn recent_buckets(&self, now: Duration) -> Vec<(Duration, WindowStats)> {
+        self.window
+            .as_ref()
+            .map_or_else(Vec::new, |window| window.buckets(now))
+    }
+
+    /// Return a snapshot of the state and counters, e.g. to persist them.
+    pub fn export_state(&self, now: Duration) -> StateSnapshot {
+        StateSnapshot {
+            state: self.state(now),
+            consecutive_failures: self.consecutive_failures,
+            consecutive_successes: self.consecutive_successes,
+            reopens: self.reopens,
+        }
+    }
+
+    /// Restore the state and counters from `snapshot`, an open state staying
+    /// open for its remaining trip timeout from `now` on.
+    pub fn import_state(&mut self, snapshot: StateSnapshot, now: Duration) {
+        self.set_state(Status::restore(snapshot.state, now), now);
+        self.consecutive_failures = snapshot.consecutive_failures;
+        self.consecutive_successes = snapshot.consecutive_successes;
+        self.half_open_calls = 0;
+        self.reopens = snapshot.reopens;
+        self.closed_at = None;
+    }
+
+    /// Return the number of transitions made so far, e.g. to tell whether the
+    /// state changed since a call was permitted.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Take the number of consecutive failures at which they reached the warn
+    /// threshold, if they did since the last time it was taken.
+    ///
+    /// The warning is raised once per crossing, i.e. again only after the
+    /// consecutive failures were reset.
+    pub fn take_warning(&mut self) -> Option<usize> {
+        self.warning.take()
+    }
+
+    /// Take the old and new states of the transitions made since the last
+    /// time they were taken, oldest first.
+    pub fn take_transitions(&mut self) -> Vec<(State, State)> {
+        mem::take(&mut self.transitions)
+    }
+
+    /// Return the total time spent in each state, including the time spent in
+    /// the current one until `now`.
+    pub fn dwell_times(&self, now: Duration) -> DwellTimes {
+        self.dwell_times
+            .with(self.state.snapshot(now), self.time_in_current_state(now))
+    }
+
+    /// Return the most recent transitions, oldest first, e.g. for a
+    /// post-incident analysis.
+    pub fn transition_history(&self) -> Vec<TransitionEvent> {
+        self.history.iter().copied().collect()
+    }
+
+    fn set_state(&mut self, state: Status, now: Duration) {
+        let from = self.state.snapshot(now);
+        self.state = state;
+        let to = self.state.snapshot(now);
+        if mem::discriminant(&from) != mem::discriminant(&to) {
+            self.dwell_times = self
+                .dwell_times
+                .with(from, now.saturating_sub(self.last_transition));
+            self.last_transition = now;
+            self.transitions.push((from, to));
+            if self.history_capacity > 0 {
+                if self.history.len() == self.history_capacity {
+                    self.history.pop_front();
+                }
+                self.history
+                    .push_back(TransitionEvent { from, to, at: now });
+            }
+            self.generation += 1;
+        }
+    }
+
+    // Return the consecutive failures tripping the circuit breaker at `now`,
+    // multiplied while settling after it closed.
+    fn max_failures(&self, now: Duration) -> usize {
+        match (self.config.settling, self.closed_at) {
+            (Some(settling), Some(closed_at))
+                if now.saturating_sub(closed_at) < settling.duration =>
+            {
+                self.config.max_failures.saturating_mul(settling.multiplier)
+            }
+            _ => self.config.max_failures,
+        }
+    }
+
+    fn transit_to_closed(&mut self, now: Duration) {
+        self.set_state(Status::Closed, now);
+        self.closed_at = Some(now);
+        self.consecutive_failures = 0;
+        self.consecutive_successes = 0;
+        self.closed_successes = 0;
+        self.half_open_calls = 0;
+        if self.config.backoff_decay_successes.is_none() {
+            self.reopens = 0;
+        }
+        self.last_error = None;
+        self.ewma = 0.0;
+        self.ewma_calls = 0;
+        self.cost = 0.0;
+        self.cost_drained_at = now;
+        if let Some(window) = self.window.as_mut() {
+            window.clear();
+        }
+    }
+
+    fn transit_to_half_open(&mut self, now: Duration) {
+        self.set_state(Status::HalfOpen, now);
+        self.consecutive_successes = 0;
+        self.half_open_calls = 0;
+    }
+
+    fn transit_to_open(&mut self, reason: TripReason, now: Duration) {
+        let backoff = self.config.backoff();
+        let delay = match self.timeout_fn.as_ref() {
+            Some(TimeoutFn(f)) => f(self.reopens),
+            None => backoff.delay(self.reopens),
+        };
+        let delay = backoff.jitter(delay, || match self.jitter_source.as_ref() {
+            Some(JitterSource(sample)) => sample(),
+            None => {
+                self.seed = self.seed.wrapping_add(GAMMA);
+                split_mix::unit_f64(split_mix::mix(self.seed))
+            }
+        });
+        let until = now.saturating_add(delay);
+        self.set_state(Status::Open(until, reason), now);
+        self.reopens = self.reopens.saturating_add(1);
+        self.consecutive_successes = 0;
+        self.half_open_calls = 0;
+    }
+
+    /// Record `n` permitted calls as success.
+    fn record_success(&mut self, slow: bool, n: usize, now: Duration) {
+        match self.state {
+            Status::Closed => {
+                let outcome = Outcome {
+                    at: now,
+                    failure: false,
+                    slow,
+                };
+                if let Some(reason) = self.record_closed(outcome, 0, n, now) {
+                    if !self.is_warming_up(now) {
+                        self.transit_to_open(reason, now);
+                    }
+                }
+            }
+            Status::HalfOpen if slow && self.config.fail_slow_probes => {
+                self.record_failure(slow, 1, 1, now);
+            }
+            Status::HalfOpen => {
+                self.release_probe();
+                self.consecutive_successes = self.consecutive_successes.saturating_add(n);
+                if self.consecutive_successes >= self.config.success_threshold {
+                    self.transit_to_closed(now);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Record `n` permitted calls as failure, each counting as `weight`
+    /// consecutive failures.
+    fn record_failure(&mut self, slow: bool, weight: usize, n: usize, now: Duration) {
+        match self.state {
+            Status::Closed => {
+                let outcome = Outcome {
+                    at: now,
+                    failure: true,
+                    slow,
+                };
+                if let Some(reason) = self.record_closed(outcome, weight, n, now) {
+                    if !self.is_warming_up(now) {
+                        self.transit_to_open(reason, now);
+                    }
+                }
+            }
+            Status::HalfOpen => {
+                self.release_probe();
+                // Reopening without closing in between advances the backoff, so
+                // stepping back first makes a failed probe wait the last timeout.
+                if self.config.backoff_on_half_open_failure == BackoffOnHalfOpenFailure::Repeat {
+                    self.reopens = self.reopens.saturating_sub(1);
+                }
+                self.transit_to_open(TripReason::HalfOpenFailure, now);
+            }
+            _ => {}
+        }
+    }
+
+    /// Tell whether the warmup is still running, during which the thresholds
+    /// never trip the circuit breaker.
+    fn is_warming_up(&self, now: Duration) -> bool {
+        self.config
+            .warmup
+            .is_some_and(|warmup| now.saturating_sub(self.created_at) < warmup)
+    }
+
+    /// Take a probe slot in the half-open state if one is available.
+    fn acquire_probe(&mut self, now: Duration) -> bool {
+        if !self.has_probe_slot(now) {
+            return false;
+        }
+        if self.is_probe_timed_out(now) {
+            // The probes holding the slots are presumed abandoned.
+            self.half_open_calls = 0;
+        }
+        self.half_open_calls += 1;
+        self.probe_acquired_at = now;
+        true
+    }
+
+    /// Tell whether a probe slot is available or would be given back.
+    fn has_probe_slot(&self, now: Duration) -> bool {
+        self.config
+            .half_open_max_calls
+            .is_none_or(|max_calls| self.half_open_calls < max_calls)
+            || self.is_probe_timed_out(now)
+    }
+
+    fn is_probe_timed_out(&self, now: Duration) -> bool {
+        self.half_open_calls > 0
+            && now.saturating_sub(self.probe_acquired_at) >= self.config.probe_timeout
+    }
+
+    fn release_probe(&mut self) {
+        self.half_open_calls = self.half_open_calls.saturating_sub(1);
+    }
+
+    /// Drain the bucket of the cost budget up to `now` and add `cost` to it,
+    /// returning its new level.
+    fn add_cost(&mut self, budget: CostBudget, cost: usize, now: Duration) -> f64 {
+        let drained = if budget.drain_time.is_zero() {
+            self.cost
+        } else {
+            let elapsed = now.saturating_sub(self.cost_drained_at);
+            budget.budget as f64 * elapsed.as_secs_f64() / budget.drain_time.as_secs_f64()
+        };
+        self.cost = (self.cost - drained).max(0.0) + cost as f64;
+        self.cost_drained_at = now;
+        self.cost
+    }
+
+    /// Record an outcome in the closed state and tell why the circuit breaker
+    /// should trip, if it should.
+    ///
+    /// Without a sliding window a slow call counts as a consecutive failure.
+    /// With one, slow calls trip on their own `slow_call_rate_threshold`. A
+    /// moving average, if set, decides instead, with a slow call counting as a
+    /// failure. A cost budget, if set, decides instead of both, with a failure
+    /// costing `weight` and a slow call 1. A policy, if set, decides instead
+    /// of all the thresholds.
+    /// A failure counts as `weight` consecutive failures, and a success which
+    /// is not slow resets them. The outcome is recorded for `n` calls at once.
+    fn record_closed(
+        &mut self,
+        outcome: Outcome,
+        weight: usize,
+        n: usize,
+        now: Duration,
+    ) -> Option<TripReason> {
+        let unhealthy = outcome.failure || outcome.slow;
+        if unhealthy {
+            self.closed_successes = 0;
+        } else if let Some(successes) = self.config.backoff_decay_successes {
+            self.closed_successes = self.closed_successes.saturating_add(n);
+            let steps = match successes {
+                0 => n,
+                successes => self.closed_successes / successes,
+            };
+            self.closed_successes = self.closed_successes.checked_rem(successes).unwrap_or(0);
+            self.reopens = self
+                .reopens
+                .saturating_sub(u32::try_from(steps).unwrap_or(u32::MAX));
+        }
+        let failures = self.consecutive_failures;
+        if outcome.failure {
+            self.consecutive_failures = self
+                .consecutive_failures
+                .saturating_add(weight.saturating_mul(n));
+        } else if outcome.slow {
+            self.consecutive_failures = self.consecutive_failures.saturating_add(n);
+        } else {
+            self.consecutive_failures = 0;
+        }
+        if let Some(threshold) = self.config.warn_threshold {
+            if failures < threshold && self.consecutive_failures >= threshold {
+                self.warning = Some(self.consecutive_failures);
+            }
+        }
+
+        if let Some(policy) = self.policy.as_ref() {
+            let mut stats = match self.window.as_mut() {
+                Some(window) => {
+                    window.record(outcome, n, now);
+                    window.stats(now)
+                }
+                None => WindowStats::default(),
+            };
+            stats.consecutive_failures = self.consecutive_failures;
+            return (unhealthy && policy.should_trip(&stats)).then_some(TripReason::Policy);
+        }
+
+        if let Some(budget) = self.config.cost_budget {
+            if let Some(window) = self.window.as_mut() {
+                window.record(outcome, n, now);
+            }
+            let cost = if outcome.failure {
+                weight
+            } else {
+                usize::from(outcome.slow)
+            };
+            let level = self.add_cost(budget, cost.saturating_mul(n), now);
+            return (unhealthy && level > budget.budget as f64).then_some(TripReason::CostBudget);
+        }
+
+        if let Some(alpha) = self.config.ewma_alpha {
+            if let Some(window) = self.window.as_mut() {
+                window.record(outcome, n, now);
+            }
+            let sample = if unhealthy { 1.0 } else { 0.0 };
+            // Averaging `n` equal samples in turn leaves `(1 - alpha)^n` of the
+            // distance between the old average and the sample.
+            let decay = powi(1.0 - alpha, u32::try_from(n).unwrap_or(u32::MAX));
+            self.ewma = sample + (self.ewma - sample) * decay;
+            self.ewma_calls = self.ewma_calls.saturating_add(n);
+            return (unhealthy
+                && self.ewma_calls >= self.config.minimum_throughput
+                && self.ewma >= self.config.failure_rate_threshold)
+                .then_some(TripReason::FailureRate);
+        }
+
+        match self.window.as_mut() {
+            Some(window) => {
+                window.record(outcome, n, now);
+                let stats = window.stats(now);
+                if !unhealthy || stats.calls < self.config.minimum_throughput {
+                    None
+                } else if stats.failure_rate() >= self.config.failure_rate_threshold {
+                    Some(TripReason::FailureRate)
+                } else if stats.slow_call_rate() >= self.config.slow_call_rate_threshold {
+                    Some(TripReason::SlowCallRate)
+                } else {
+                    None
+                }
+            }
+            None => (unhealthy && self.consecutive_failures >= self.max_failures(now))
+                .then_some(TripReason::ConsecutiveFailures),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SlidingWindow;
+    use crate::policy::{AnyOf, ConsecutiveFailures, FailureRate};
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    // A sample for the recovery ramp, which no test below configures.
+    fn sample() -> f64 {
+        0.0
+    }
+
+    // Record the outcomes of `calls`, `true` for a success, and return the
+    // resulting state.
+    fn record(machine: &mut Machine, calls: &[bool], now: Duration) -> State {
+        for &success in calls {
+            if success {
+                machine.on_success(false, now);
+            } else {
+                machine.on_error(false, 1, now);
+            }
+        }
+        machine.state(now)
+    }
+
+    #[test]
+    fn success_resets_the_consecutive_failures() {
+        let config = Config {
+            max_failures: 3,
+            ..Config::default()
+        };
+        let mut machine = Machine::new(config, secs(0));
+        let calls = [false, false, true, false, false];
+        assert_eq!(record(&mut machine, &calls, secs(0)), State::Closed);
+        assert_eq!(machine.consecutive_failures(), 2);
+    }
+
+    #[test]
+    fn either_policy_trips_the_breaker() {
+        let config = Config {
+            max_failures: 1,
+            window: Some(SlidingWindow::Count(10)),
+            ..Config::default()
+        };
+        let mut machine = Machine::new(config, secs(0));
+        machine.set_policy(Box::new(
+            AnyOf::new().or(ConsecutiveFailures(5)).or(FailureRate(0.6)),
+        ));
+        let calls = [true, true, false, true, false, true, false, false, false];
+        assert_eq!(record(&mut machine, &calls, secs(0)), State::Closed);
+        // 6 out of the last 10 calls failed, but only 4 in a row.
+        assert!(matches!(
+            record(&mut machine, &[false], secs(0)),
+            State::Open {
+                reason: TripReason::Policy,
+                ..
+            }
+        ));
+
+        machine.reset(secs(0));
+        let calls = [true; 10];
+        assert_eq!(record(&mut machine, &calls, secs(0)), State::Closed);
+        let calls = [false; 4];
+        assert_eq!(record(&mut machine, &calls, secs(0)), State::Closed);
+        // 5 in a row, but only half of the last 10 calls failed.
+        assert!(matches!(
+            record(&mut machine, &[false], secs(0)),
+            State::Open { .. }
+        ));
+    }
+
+    #[test]
+    fn zero_trip_timeout_probes_on_the_next_call() {
+        let config = Config {
+            max_failures: 1,
+            trip_timeout: Duration::ZERO,
+            ..Config::default()
+        };
+        let mut machine = Machine::new(config, secs(0));
+        machine.on_error(false, 1, secs(5));
+        assert_eq!(machine.retry_after(secs(5)), Some(Duration::ZERO));
+        assert!(machine.is_call_permitted(secs(5), sample));
+        assert_eq!(machine.state(secs(5)), State::HalfOpen);
+
+        machine.on_error(false, 1, secs(5));
+        assert!(machine.is_call_permitted(secs(5), sample));
+        machine.on_success(false, secs(5));
+        assert_eq!(machine.state(secs(5)), State::Closed);
+    }
+
+    // Fail a probe at `now` and return the timeout the circuit breaker reopens
+    // for.
+    fn fail_probe(machine: &mut Machine, now: Duration) -> Duration {
+        assert!(machine.is_call_permitted(now, sample));
+        machine.on_error(false, 1, now);
+        machine.retry_after(now).unwrap()
+    }
+
+    fn backoff_config(mode: BackoffOnHalfOpenFailure) -> Config {
+        Config {
+            max_failures: 1,
+            trip_timeout: secs(1),
+            backoff_multiplier: 2.0,
+            backoff_on_half_open_failure: mode,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn backoff_stays_elevated_until_enough_closed_successes() {
+        let config = Config {
+            backoff_decay_successes: Some(3),
+            ..backoff_config(BackoffOnHalfOpenFailure::Escalate)
+        };
+        let trip_after = |successes: usize| {
+            let mut machine = Machine::new(config.clone(), secs(0));
+            machine.on_error(false, 1, secs(0));
+            assert_eq!(fail_probe(&mut machine, secs(1)), secs(2));
+            assert!(machine.is_call_permitted(secs(3), sample));
+            machine.on_success(false, secs(3));
+            assert_eq!(machine.state(secs(3)), State::Closed);
+            for _ in 0..successes {
+                machine.on_success(false, secs(3));
+            }
+            machine.on_error(false, 1, secs(3));
+            machine.retry_after(secs(3)).unwrap()
+        };
+
+        assert_eq!(trip_after(0), secs(4));
+        assert_eq!(trip_after(2), secs(4));
+        assert_eq!(trip_after(3), secs(2));
+        assert_eq!(trip_after(6), secs(1));
+    }
+
+    #[test]
+    fn a_huge_batch_is_recorded_at_once() {
+        let mut machine = Machine::new(Config::default(), secs(0));
+        machine.on_batch(false, false, 3, secs(0));
+        assert_eq!(machine.consecutive_failures(), 3);
+        machine.on_batch(true, false, usize::MAX, secs(0));
+        assert_eq!(machine.consecutive_failures(), 0);
+
+        let mut machine = Machine::new(Config::default(), secs(0));
+        machine.on_batch(false, false, usize::MAX, secs(0));
+        assert!(matches!(machine.state(secs(0)), State::Open { .. }));
+    }
+
+    #[test]
+    fn a_batch_gives_back_one_probe_slot() {
+        let config = Config {
+            max_failures: 1,
+            trip_timeout: secs(1),
+            success_threshold: 3,
+            ..Config::default()
+        };
+        let mut machine = Machine::new(config, secs(0));
+        machine.on_error(false, 1, secs(0));
+        assert!(machine.is_call_permitted(secs(1), sample));
+        machine.on_batch(true, false, 2, secs(1));
+        assert_eq!(machine.state(secs(1)), State::HalfOpen);
+        assert!(machine.is_call_permitted(secs(1), sample));
+        machine.on_batch(true, false, 1, secs(1));
+        assert_eq!(machine.state(secs(1)), State::Closed);
+    }
+
+    #[test]
+    fn abandoned_probe_slots_are_given_back_after_the_probe_timeout() {
+        let config = Config {
+            max_failures: 1,
+            trip_timeout: secs(1),
+            probe_timeout: secs(5),
+            ..Config::default()
+        };
+        let mut machine = Machine::new(config, secs(0));
+        machine.on_error(false, 1, secs(0));
+        assert!(machine.is_call_permitted(secs(1), sample));
+        assert!(!machine.would_permit(secs(5)));
+        assert!(!machine.is_call_permitted(secs(5), sample));
+
+        assert!(machine.would_permit(secs(6)));
+        assert!(machine.is_call_permitted(secs(6), sample));
+        assert!(!machine.is_call_permitted(secs(6), sample));
+        machine.on_success(false, secs(7));
+        assert_eq!(machine.state(secs(7)), State::Closed);
+    }
+}
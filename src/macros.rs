@@ -0,0 +1,53 @@
+//! Macros sparing the closures around calls made within a circuit breaker.
+
+/// Call an expression returning a `Result` within a circuit breaker.
+///
+/// `cb_call!(breaker, expr)` expands to `breaker.call(|| expr)` for any
+/// `CircuitBreaker`, and `cb_call!(breaker, timeout = duration, expr)` to
+/// `breaker.call_timeout(duration, move || expr)` for a `StateMachine`, so the
+/// expression then moves what it captures.
+#[macro_export]
+macro_rules! cb_call {
+    ($breaker:expr, timeout = $timeout:expr, $call:expr $(,)?) => {
+        $breaker.call_timeout($timeout, move || $call)
+    };
+    ($breaker:expr, $call:expr $(,)?) => {
+        $crate::CircuitBreaker::call(&$breaker, || $call)
+    };
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{Error, StateMachine};
+
+    fn parse(input: &str) -> Result<u32, std::num::ParseIntError> {
+        input.parse()
+    }
+
+    #[test]
+    fn behaves_like_a_direct_call() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        assert!(matches!(cb_call!(breaker, parse("7")), Ok(7)));
+        assert!(matches!(
+            cb_call!(breaker, parse("x")),
+            Err(Error::Inner(_))
+        ));
+        assert!(matches!(
+            cb_call!(breaker, parse("7")),
+            Err(Error::Rejected { .. })
+        ));
+    }
+
+    #[test]
+    fn calls_with_a_timeout() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        let input = String::from("7");
+        assert!(matches!(
+            cb_call!(breaker, timeout = Duration::from_secs(1), parse(&input)),
+            Ok(7)
+        ));
+        assert!(breaker.is_closed());
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A snapshot of the cumulative counters of a `StateMachine`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Metrics {
+    /// The number of calls, whether permitted or rejected.
+    pub total_calls: u64,
+    /// The number of permitted calls recorded as success.
+    pub successful_calls: u64,
+    /// The number of permitted calls recorded as failure.
+    pub failed_calls: u64,
+    /// The number of calls rejected without being executed.
+    pub rejected_calls: u64,
+    /// The number of times the state changed.
+    pub state_transitions: u64,
+    /// The number of calls in the half-open state recorded as success.
+    pub half_open_successes: u64,
+    /// The number of calls in the half-open state recorded as failure.
+    pub half_open_failures: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    pub(crate) total_calls: AtomicU64,
+    pub(crate) successful_calls: AtomicU64,
+    pub(crate) failed_calls: AtomicU64,
+    pub(crate) rejected_calls: AtomicU64,
+    pub(crate) state_transitions: AtomicU64,
+    pub(crate) half_open_successes: AtomicU64,
+    pub(crate) half_open_failures: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn increment(counter: &AtomicU64) {
+        Self::add(counter, 1);
+    }
+
+    pub(crate) fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Metrics {
+        Metrics {
+            total_calls: self.total_calls.load(Ordering::Relaxed),
+            successful_calls: self.successful_calls.load(Ordering::Relaxed),
+            failed_calls: self.failed_calls.load(Ordering::Relaxed),
+            rejected_calls: self.rejected_calls.load(Ordering::Relaxed),
+            state_transitions: self.state_transitions.load(Ordering::Relaxed),
+            half_open_successes: self.half_open_successes.load(Ordering::Relaxed),
+            half_open_failures: self.half_open_failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Emit a call with its `result`, i.e. `success`, `failure` or `rejected`, to
+/// the `metrics` facade as `circuit_breaker_calls_total`.
+#[cfg(feature = "metrics")]
+pub(crate) fn export_call(name: Option<&str>, result: &'static str) {
+    ::metrics::counter!(
+        "circuit_breaker_calls_total",
+        "name" => name.unwrap_or_default().to_string(),
+        "result" => result,
+    )
+    .increment(1);
+}
+
+#[cfg(not(feature = "metrics"))]
+#[inline(always)]
+pub(crate) fn export_call(_name: Option<&str>, _result: &'static str) {}
+
+/// Emit the discriminant of the new state to the `metrics` facade as the
+/// `circuit_breaker_state` gauge: 0 when closed, 1 when open, 2 when half-open
+/// and 3 when isolated.
+#[cfg(feature = "metrics")]
+pub(crate) fn export_state(name: Option<&str>, status: u8) {
+    ::metrics::gauge!(
+        "circuit_breaker_state",
+        "name" => name.unwrap_or_default().to_string(),
+    )
+    .set(status);
+}
+
+#[cfg(not(feature = "metrics"))]
+#[inline(always)]
+pub(crate) fn export_state(_name: Option<&str>, _status: u8) {}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use std::time::Duration;
+
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::MetricKind;
+
+    use crate::circuit_breaker::CircuitBreaker;
+    use crate::state_machine::StateMachine;
+
+    #[test]
+    fn exports_calls_and_state_to_the_recorder() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        ::metrics::with_local_recorder(&recorder, || {
+            let breaker = StateMachine::builder()
+                .name("payments")
+                .max_failures(1)
+                .trip_timeout(Duration::from_secs(60))
+                .build()
+                .unwrap();
+            assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+            let _ = breaker.call(|| Err::<(), _>(()));
+            assert!(breaker.call(|| Ok::<_, ()>(())).is_err());
+        });
+
+        let mut calls = Vec::new();
+        let mut state = None;
+        for (key, _, _, value) in snapshotter.snapshot().into_vec() {
+            let labels: Vec<_> = key
+                .key()
+                .labels()
+                .map(|label| (label.key().to_string(), label.value().to_string()))
+                .collect();
+            assert!(labels.contains(&("name".to_string(), "payments".to_string())));
+            match (key.kind(), key.key().name(), value) {
+                (MetricKind::Counter, "circuit_breaker_calls_total", DebugValue::Counter(n)) => {
+                    let (_, result) = labels.iter().find(|(key, _)| key == "result").unwrap();
+                    calls.push((result.clone(), n));
+                }
+                (MetricKind::Gauge, "circuit_breaker_state", DebugValue::Gauge(value)) => {
+                    state = Some(value.into_inner());
+                }
+                metric => panic!("unexpected metric {:?}", metric),
+            }
+        }
+        calls.sort();
+        assert_eq!(
+            calls,
+            [
+                ("failure".to_string(), 1),
+                ("rejected".to_string(), 1),
+                ("success".to_string(), 1),
+            ]
+        );
+        assert_eq!(state, Some(1.0));
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use crate::state_machine::StateMachine;
+
+/// A permission to make one call, for code which does the work inline rather
+/// than in a closure.
+///
+/// The outcome is recorded with `record_success`, `record_failure` or
+/// `record_error`. A permit dropped without recording an outcome, e.g. because
+/// the work panicked or returned early, is recorded as failure.
+///
+/// An outcome recorded after the circuit breaker changed its state since the
+/// permit was acquired, e.g. because it tripped meanwhile, is only counted in
+/// the metrics: it belongs to the previous state and must not affect the
+/// current one, e.g. close a circuit breaker which reopened.
+#[must_use = "dropping a permit records a failure"]
+pub struct Permit<'a> {
+    breaker: &'a StateMachine,
+    // When the permit was acquired, as the time since the breaker's epoch.
+    start: Duration,
+    // The breaker's generation when the permit was acquired.
+    generation: u64,
+    recorded: bool,
+}
+
+impl<'a> Permit<'a> {
+    pub(crate) fn new(breaker: &'a StateMachine, start: Duration, generation: u64) -> Self {
+        Permit {
+            breaker,
+            start,
+            generation,
+            recorded: false,
+        }
+    }
+
+    // Take the generation the permit was acquired at and the time elapsed
+    // since, leaving the outcome to be recorded by the caller.
+    pub(crate) fn finish(mut self) -> (u64, Duration) {
+        self.recorded = true;
+        (self.generation, self.breaker.elapsed(self.start))
+    }
+
+    /// Record the permitted call as success.
+    pub fn record_success(mut self) {
+        self.recorded = true;
+        self.breaker
+            .on_success_since(self.generation, self.breaker.elapsed(self.start));
+    }
+
+    /// Record the permitted call as failure.
+    pub fn record_failure(mut self) {
+        self.recorded = true;
+        self.breaker
+            .on_error_since(self.generation, self.breaker.elapsed(self.start), 1);
+    }
+
+    /// Record the permitted call as failed with `err`, keeping its message as
+    /// the last error. It counts as success if the predicate set with
+    /// `trip_only_on` rejects it.
+    pub fn record_error<E: 'static>(mut self, err: &E) {
+        if !self.breaker.trips_on(err) {
+            self.record_success();
+            return;
+        }
+        self.recorded = true;
+        let message = self.breaker.describe(err);
+        self.breaker.on_error_described(
+            self.generation,
+            self.breaker.elapsed(self.start),
+            1,
+            message,
+        );
+    }
+
+    /// Record the permitted call as failure counting as `weight` consecutive
+    /// failures. A weight of `0` records it as success.
+    pub fn record_failure_weighted(mut self, weight: usize) {
+        self.recorded = true;
+        let elapsed = self.breaker.elapsed(self.start);
+        match weight {
+            0 => self.breaker.on_success_since(self.generation, elapsed),
+            weight => self
+                .breaker
+                .on_error_since(self.generation, elapsed, weight),
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if !self.recorded {
+            self.breaker
+                .on_error_since(self.generation, self.breaker.elapsed(self.start), 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::state::State;
+
+    fn breaker() -> StateMachine {
+        StateMachine::new(2, Duration::from_secs(60))
+    }
+
+    #[test]
+    fn records_an_explicit_success() {
+        let breaker = breaker();
+        breaker.acquire().unwrap().record_failure();
+        breaker.acquire().unwrap().record_success();
+
+        assert_eq!(breaker.current_state(), State::Closed);
+        assert_eq!(breaker.metrics().successful_calls, 1);
+    }
+
+    #[test]
+    fn records_an_explicit_failure() {
+        let breaker = breaker();
+        breaker.acquire().unwrap().record_failure();
+        breaker.acquire().unwrap().record_failure();
+
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+        assert!(breaker.acquire().is_none());
+    }
+
+    #[test]
+    fn dropping_without_an_outcome_records_a_failure() {
+        let breaker = breaker();
+        let permit = breaker.acquire().unwrap();
+        drop(permit);
+
+        assert_eq!(breaker.metrics().failed_calls, 1);
+        let _ = breaker.acquire();
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn stale_permit_does_not_affect_the_new_state() {
+        let breaker = breaker();
+        let permit = breaker.acquire().unwrap();
+        breaker.trip();
+
+        permit.record_success();
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+        let metrics = breaker.metrics();
+        assert_eq!(metrics.successful_calls, 1);
+        assert_eq!(metrics.half_open_successes, 0);
+        assert_eq!(metrics.state_transitions, 1);
+        assert!(breaker.acquire().is_none());
+    }
+}
@@ -0,0 +1,145 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::window::WindowStats;
+
+/// A condition on the recent outcomes of calls which trips the circuit
+/// breaker, replacing the built-in thresholds when set.
+///
+/// A policy is only consulted after a failed or slow call in the closed state.
+/// The counts of calls, failures and slow calls are those of the sliding
+/// window, so they stay 0 unless one is configured, and `minimum_throughput`
+/// does not apply.
+pub trait Policy: Send + Sync {
+    /// Tell whether the circuit breaker should trip.
+    fn should_trip(&self, stats: &WindowStats) -> bool;
+}
+
+impl fmt::Debug for dyn Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Policy")
+    }
+}
+
+impl<F> Policy for F
+where
+    F: Fn(&WindowStats) -> bool + Send + Sync,
+{
+    fn should_trip(&self, stats: &WindowStats) -> bool {
+        self(stats)
+    }
+}
+
+/// Trip once the given number of consecutive failures is reached.
+#[derive(Debug, Clone, Copy)]
+pub struct ConsecutiveFailures(pub usize);
+
+impl Policy for ConsecutiveFailures {
+    fn should_trip(&self, stats: &WindowStats) -> bool {
+        stats.consecutive_failures >= self.0
+    }
+}
+
+/// Trip once the failure rate, between 0.0 and 1.0, within the sliding
+/// window is reached.
+#[derive(Debug, Clone, Copy)]
+pub struct FailureRate(pub f64);
+
+impl Policy for FailureRate {
+    fn should_trip(&self, stats: &WindowStats) -> bool {
+        stats.calls > 0 && stats.failure_rate() >= self.0
+    }
+}
+
+/// Trip once the slow call rate, between 0.0 and 1.0, within the sliding
+/// window is reached.
+#[derive(Debug, Clone, Copy)]
+pub struct SlowCallRate(pub f64);
+
+impl Policy for SlowCallRate {
+    fn should_trip(&self, stats: &WindowStats) -> bool {
+        stats.calls > 0 && stats.slow_call_rate() >= self.0
+    }
+}
+
+/// Trip once any of the policies would, e.g. on 5 consecutive failures or a
+/// failure rate of 60%.
+#[derive(Debug, Default)]
+pub struct AnyOf(Vec<Box<dyn Policy>>);
+
+impl AnyOf {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a policy to the ones of which any trips the circuit breaker.
+    pub fn or(mut self, policy: impl Policy + 'static) -> Self {
+        self.0.push(Box::new(policy));
+        self
+    }
+}
+
+impl Policy for AnyOf {
+    fn should_trip(&self, stats: &WindowStats) -> bool {
+        self.0.iter().any(|policy| policy.should_trip(stats))
+    }
+}
+
+/// Trip once all of the policies would. Without any policies, it never trips.
+#[derive(Debug, Default)]
+pub struct AllOf(Vec<Box<dyn Policy>>);
+
+impl AllOf {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a policy to the ones which all must trip the circuit breaker.
+    pub fn and(mut self, policy: impl Policy + 'static) -> Self {
+        self.0.push(Box::new(policy));
+        self
+    }
+}
+
+impl Policy for AllOf {
+    fn should_trip(&self, stats: &WindowStats) -> bool {
+        !self.0.is_empty() && self.0.iter().all(|policy| policy.should_trip(stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(calls: usize, failures: usize, consecutive_failures: usize) -> WindowStats {
+        WindowStats {
+            calls,
+            failures,
+            slow_calls: 0,
+            consecutive_failures,
+        }
+    }
+
+    fn either() -> AnyOf {
+        AnyOf::new().or(ConsecutiveFailures(5)).or(FailureRate(0.6))
+    }
+
+    #[test]
+    fn any_of_trips_on_either_policy() {
+        assert!(!either().should_trip(&stats(10, 5, 4)));
+        assert!(either().should_trip(&stats(100, 10, 5)));
+        assert!(either().should_trip(&stats(10, 6, 1)));
+    }
+
+    #[test]
+    fn all_of_trips_on_both_policies_only() {
+        let both = AllOf::new()
+            .and(ConsecutiveFailures(5))
+            .and(FailureRate(0.6));
+        assert!(!both.should_trip(&stats(100, 10, 5)));
+        assert!(!both.should_trip(&stats(10, 6, 1)));
+        assert!(both.should_trip(&stats(10, 6, 5)));
+        assert!(!AllOf::new().should_trip(&stats(10, 10, 10)));
+    }
+}
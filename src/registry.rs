@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use crate::builder::StateMachineBuilder;
+use crate::config::ConfigError;
+use crate::state_machine::StateMachine;
+use crate::sync::Mutex;
+
+/// A thread-safe registry of circuit breakers looked up by name, e.g. one per
+/// downstream service.
+#[derive(Default)]
+pub struct CircuitBreakerRegistry {
+    breakers: Mutex<HashMap<String, StateMachine>>,
+}
+
+impl CircuitBreakerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the circuit breaker registered under `name`, building it from
+    /// `config` first if there is none yet.
+    ///
+    /// The returned handle shares its state with every other handle returned
+    /// for the same name. A circuit breaker built without a name is named
+    /// `name`. `config` is only checked if it is built, and an invalid one
+    /// registers nothing.
+    pub fn get_or_create(
+        &self,
+        name: &str,
+        mut config: StateMachineBuilder,
+    ) -> Result<StateMachine, ConfigError> {
+        let mut breakers = self.breakers.lock();
+        if let Some(breaker) = breakers.get(name) {
+            return Ok(breaker.clone());
+        }
+        config.name.get_or_insert_with(|| name.to_string());
+        let breaker = config.build()?;
+        breakers.insert(name.to_string(), breaker.clone());
+        Ok(breaker)
+    }
+
+    /// Iterate over a snapshot of all registered circuit breakers and their
+    /// names, e.g. to export metrics in bulk.
+    pub fn iter(&self) -> impl Iterator<Item = (String, StateMachine)> {
+        let breakers = self.breakers.lock();
+        breakers
+            .iter()
+            .map(|(name, breaker)| (name.clone(), breaker.clone()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Reset all registered circuit breakers to closed.
+    ///
+    /// Circuit breakers registered while resetting may be left out.
+    pub fn reset_all(&self) {
+        for (_, breaker) in self.iter() {
+            breaker.reset();
+        }
+    }
+
+    /// Trip all registered circuit breakers open, e.g. during an incident.
+    ///
+    /// Isolated circuit breakers stay isolated, so that the trip does not end
+    /// their maintenance once its timeout expires. Circuit breakers registered
+    /// while tripping may be left out.
+    pub fn trip_all(&self) {
+        for (_, breaker) in self.iter() {
+            breaker.trip();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit_breaker::CircuitBreaker;
+    use crate::state::{State, TripReason};
+
+    #[test]
+    fn get_or_create_returns_the_same_breaker_for_a_name() {
+        let registry = CircuitBreakerRegistry::new();
+        let first = registry
+            .get_or_create("payments", StateMachine::builder().max_failures(3))
+            .unwrap();
+        let second = registry
+            .get_or_create("payments", StateMachine::builder().max_failures(7))
+            .unwrap();
+        let other = registry
+            .get_or_create("search", StateMachine::builder())
+            .unwrap();
+
+        // The first configuration wins and both handles share its state.
+        for _ in 0..3 {
+            let _ = first.call(|| Err::<(), _>(()));
+        }
+        assert!(matches!(second.current_state(), State::Open { .. }));
+        assert_eq!(other.current_state(), State::Closed);
+        assert_eq!(registry.iter().count(), 2);
+    }
+
+    #[test]
+    fn trip_all_opens_every_breaker() {
+        let registry = CircuitBreakerRegistry::new();
+        for name in ["payments", "search", "users"] {
+            registry
+                .get_or_create(name, StateMachine::builder())
+                .unwrap();
+        }
+
+        registry.trip_all();
+        for (name, breaker) in registry.iter() {
+            assert!(
+                matches!(breaker.current_state(), State::Open { .. }),
+                "{} is not open",
+                name
+            );
+            assert_eq!(breaker.trip_reason(), Some(TripReason::Manual));
+        }
+
+        registry.reset_all();
+        assert!(registry
+            .iter()
+            .all(|(_, breaker)| breaker.current_state() == State::Closed));
+    }
+
+    #[test]
+    fn trip_all_keeps_isolated_breakers_isolated() {
+        let registry = CircuitBreakerRegistry::new();
+        let isolated = registry
+            .get_or_create("isolated", StateMachine::builder())
+            .unwrap();
+        let open = registry
+            .get_or_create("open", StateMachine::builder())
+            .unwrap();
+        isolated.isolate();
+
+        registry.trip_all();
+        assert_eq!(isolated.current_state(), State::ForcedOpen);
+        assert!(matches!(open.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn get_or_create_names_an_unnamed_breaker() {
+        let registry = CircuitBreakerRegistry::new();
+        let unnamed = registry
+            .get_or_create("payments", StateMachine::builder())
+            .unwrap();
+        let named = registry
+            .get_or_create("search", StateMachine::builder().name("search-api"))
+            .unwrap();
+
+        assert_eq!(unnamed.name(), Some("payments"));
+        assert_eq!(named.name(), Some("search-api"));
+    }
+
+    #[test]
+    fn get_or_create_rejects_an_invalid_config() {
+        let registry = CircuitBreakerRegistry::new();
+        let result = registry.get_or_create("payments", StateMachine::builder().max_failures(0));
+        assert_eq!(result.err(), Some(ConfigError::ZeroMaxFailures));
+        assert_eq!(registry.iter().count(), 0);
+
+        // An invalid config is not checked once the name is registered.
+        registry
+            .get_or_create("payments", StateMachine::builder())
+            .unwrap();
+        let result = registry.get_or_create("payments", StateMachine::builder().max_failures(0));
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,191 @@
+use std::thread;
+use std::time::Duration;
+
+use crate::circuit_breaker::CircuitBreaker;
+use crate::error::Error;
+use crate::state_machine::{every_error, StateMachine};
+
+const DEFAULT_MAX_RETRIES: u32 = 2;
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// A `StateMachine` which retries a failed call before recording it, so that
+/// transient blips do not count against the circuit breaker but persistent
+/// failures do.
+///
+/// A call asks permission once and is recorded once: as success if any attempt
+/// succeeds, and as failure if the last retry fails too. The elapsed time of
+/// the call covers all its attempts and the waits in between.
+///
+/// Only a mutable function can be called again, so as a `CircuitBreaker` it
+/// retries in `call_mut`, while the trait methods taking a function which can
+/// only be called once make a single attempt.
+#[derive(Clone)]
+pub struct RetryingBreaker {
+    breaker: StateMachine,
+    max_retries: u32,
+    retry_backoff: Duration,
+}
+
+impl RetryingBreaker {
+    /// Retry calls within `breaker` up to 2 times, waiting 50 milliseconds
+    /// before the first retry.
+    pub fn new(breaker: StateMachine) -> Self {
+        RetryingBreaker {
+            breaker,
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+        }
+    }
+
+    /// Set how many times a failed call is retried before it is recorded as
+    /// failure.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set how long to wait before the first retry, doubling before every
+    /// further one.
+    pub fn retry_backoff(mut self, retry_backoff: Duration) -> Self {
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    /// Return the circuit breaker the calls are recorded in.
+    pub fn breaker(&self) -> &StateMachine {
+        &self.breaker
+    }
+
+    /// Call a given function within Circuit Breaker, retrying it while it
+    /// fails up to `max_retries` times.
+    ///
+    /// Return:
+    ///     the result of the first successful attempt, or the error of the last
+    ///     one.
+    ///     `Error::Rejected` if a call is prohibited, in which case `f` is not
+    ///     called.
+    pub fn call<F, T, E>(&self, mut f: F) -> Result<T, Error<E>>
+    where
+        F: FnMut() -> Result<T, E>,
+        T: 'static,
+        E: 'static,
+    {
+        self.call_mut(&mut f)
+    }
+}
+
+impl CircuitBreaker for RetryingBreaker {
+    fn is_call_permitted(&self) -> bool {
+        self.breaker.is_call_permitted()
+    }
+
+    /// Call a given function within Circuit Breaker, retrying it while it
+    /// fails up to `max_retries` times, like `RetryingBreaker::call`.
+    fn call_mut<F, T, E>(&self, f: &mut F) -> Result<T, Error<E>>
+    where
+        F: FnMut() -> Result<T, E>,
+        T: 'static,
+        E: 'static,
+    {
+        self.breaker.run(
+            || {
+                let mut backoff = self.retry_backoff;
+                for _ in 0..self.max_retries {
+                    match f() {
+                        Err(_) => {
+                            thread::sleep(backoff);
+                            backoff = backoff.saturating_mul(2);
+                        }
+                        result => return result.map_err(Error::Inner),
+                    }
+                }
+                f().map_err(Error::Inner)
+            },
+            every_error,
+        )
+    }
+
+    /// Call a given function within Circuit Breaker without retrying it, as it
+    /// can only be called once.
+    fn call_weighted<W, F, T, E>(&self, weigh: W, f: F) -> Result<T, Error<E>>
+    where
+        W: Fn(&E) -> usize,
+        F: FnOnce() -> Result<T, E>,
+        T: 'static,
+        E: 'static,
+    {
+        self.breaker.call_weighted(weigh, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::State;
+
+    fn retrying() -> RetryingBreaker {
+        RetryingBreaker::new(StateMachine::new(2, Duration::from_secs(60)))
+            .retry_backoff(Duration::ZERO)
+    }
+
+    #[test]
+    fn a_blip_is_recorded_as_a_single_success() {
+        let breaker = retrying();
+        let mut attempts = 0;
+        let result = breaker.call(|| {
+            attempts += 1;
+            if attempts <= 2 {
+                Err("refused")
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert!(matches!(result, Ok(3)));
+
+        let metrics = breaker.breaker().metrics();
+        assert_eq!(metrics.total_calls, 1);
+        assert_eq!(metrics.successful_calls, 1);
+        assert_eq!(metrics.failed_calls, 0);
+    }
+
+    #[test]
+    fn a_persistent_failure_is_recorded_once() {
+        let breaker = retrying();
+        let mut attempts = 0;
+        let result = breaker.call(|| {
+            attempts += 1;
+            Err::<(), _>("refused")
+        });
+        assert!(matches!(result, Err(Error::Inner("refused"))));
+        assert_eq!(attempts, 3);
+        assert_eq!(breaker.breaker().metrics().failed_calls, 1);
+        assert!(breaker.breaker().is_closed());
+    }
+
+    #[test]
+    fn retries_as_a_circuit_breaker_in_call_mut_only() {
+        fn attempts<B: CircuitBreaker>(breaker: &B, retry: bool) -> u32 {
+            let mut attempts = 0;
+            let mut attempt = || {
+                attempts += 1;
+                Err::<(), _>("refused")
+            };
+            let _ = if retry {
+                breaker.call_mut(&mut attempt)
+            } else {
+                breaker.call(attempt)
+            };
+            attempts
+        }
+
+        let breaker = retrying();
+        assert_eq!(attempts(&breaker, true), 3);
+        assert_eq!(attempts(&breaker, false), 1);
+        assert!(matches!(
+            breaker.breaker().current_state(),
+            State::Open { .. }
+        ));
+        assert!(!CircuitBreaker::is_call_permitted(&breaker));
+        assert_eq!(attempts(&breaker, true), 0);
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::split_mix::{self, GAMMA};
+
+/// A source of randomness for a `StateMachine`.
+///
+/// It is the single source of all randomized behavior: it admits calls during
+/// a recovery ramp and jitters the trip timeout on every trip. Neither `SystemRng` nor `SeededRng` needs an
+/// operating system entropy source beyond what `std` already uses for hashing.
+pub trait Rng: Send + Sync {
+    /// Return a uniformly distributed number in `[0.0, 1.0)`.
+    fn next_f64(&self) -> f64;
+}
+
+impl fmt::Debug for dyn Rng {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Rng")
+    }
+}
+
+/// A SplitMix64 generator which can be shared across threads.
+#[derive(Debug)]
+struct SplitMix64 {
+    state: AtomicU64,
+}
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        SplitMix64 {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    fn next_u64(&self) -> u64 {
+        split_mix::mix(
+            self.state
+                .fetch_add(GAMMA, Ordering::Relaxed)
+                .wrapping_add(GAMMA),
+        )
+    }
+
+    fn next_f64(&self) -> f64 {
+        split_mix::unit_f64(self.next_u64())
+    }
+}
+
+/// An `Rng` seeded randomly when it is created.
+#[derive(Debug)]
+pub struct SystemRng {
+    inner: SplitMix64,
+}
+
+impl SystemRng {
+    pub fn new() -> Self {
+        SystemRng {
+            inner: SplitMix64::new(RandomState::new().hash_one(0u64)),
+        }
+    }
+}
+
+impl Default for SystemRng {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rng for SystemRng {
+    fn next_f64(&self) -> f64 {
+        self.inner.next_f64()
+    }
+}
+
+/// An `Rng` producing the same sequence for the same seed.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug)]
+pub struct SeededRng {
+    inner: SplitMix64,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        SeededRng {
+            inner: SplitMix64::new(seed),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Rng for SeededRng {
+    fn next_f64(&self) -> f64 {
+        self.inner.next_f64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_rng_repeats_its_sequence() {
+        let sequence = |seed| {
+            let rng = SeededRng::new(seed);
+            (0..100).map(|_| rng.next_f64()).collect::<Vec<_>>()
+        };
+        let first = sequence(7);
+        assert_eq!(sequence(7), first);
+        assert_ne!(sequence(8), first);
+        assert!(first.iter().all(|x| (0.0..1.0).contains(x)));
+    }
+}
@@ -0,0 +1,18 @@
+//! The steps of a SplitMix64 generator, which is small, fast and good enough to
+//! decide whether to admit a call or how to jitter a timeout.
+
+/// The increment of the generator's state per number.
+pub(crate) const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Turn the generator's state into the next number.
+pub(crate) fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Turn a number into a uniformly distributed one in `[0.0, 1.0)`.
+pub(crate) fn unit_f64(z: u64) -> f64 {
+    // Use the upper 53 bits, the precision of an `f64`'s mantissa.
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,153 @@
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A snapshot of a `CircuitBreaker`'s state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum State {
+    /// The circuit breaker is closed and allowing requests to pass through.
+    Closed,
+    /// The circuit breaker is open and blocking requests for the `remaining` duration.
+    Open {
+        #[cfg_attr(feature = "serde", serde(with = "crate::config::duration_ms"))]
+        remaining: Duration,
+        reason: TripReason,
+    },
+    /// The circuit breaker is half-open and allowing requests to pass through.
+    HalfOpen,
+    /// The circuit breaker was isolated and is blocking requests until reset.
+    ForcedOpen,
+}
+
+impl State {
+    /// Return the kind of this state, without its data.
+    pub fn label(&self) -> StateLabel {
+        match self {
+            State::Closed => StateLabel::Closed,
+            State::Open { .. } => StateLabel::Open,
+            State::HalfOpen => StateLabel::HalfOpen,
+            State::ForcedOpen => StateLabel::ForcedOpen,
+        }
+    }
+}
+
+/// The kind of a `CircuitBreaker`'s state, without the data of a `State`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum StateLabel {
+    Closed,
+    Open,
+    HalfOpen,
+    ForcedOpen,
+}
+
+/// Why a `CircuitBreaker` opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum TripReason {
+    /// Too many consecutive failures in the closed state.
+    ConsecutiveFailures,
+    /// The failure rate of the sliding window reached its threshold.
+    FailureRate,
+    /// The slow call rate of the sliding window reached its threshold.
+    SlowCallRate,
+    /// A call failed in the half-open state.
+    HalfOpenFailure,
+    /// The circuit breaker was tripped by hand.
+    Manual,
+    /// The `Policy` set on the circuit breaker tripped it.
+    Policy,
+    /// The cost of recent failures exceeded the cost budget.
+    CostBudget,
+}
+
+/// A transition between two states, as kept in the transition history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransitionEvent {
+    /// The state before the transition.
+    pub from: State,
+    /// The state after the transition.
+    pub to: State,
+    /// When the transition happened, as the time since the circuit breaker's
+    /// epoch.
+    pub at: Duration,
+}
+
+/// The total time a circuit breaker spent in each state since it was created,
+/// e.g. for SLA reporting.
+///
+/// The time spent isolated counts as open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DwellTimes {
+    /// The time spent closed.
+    pub closed: Duration,
+    /// The time spent open, up to the probe which made it go half-open.
+    pub open: Duration,
+    /// The time spent half-open.
+    pub half_open: Duration,
+}
+
+impl DwellTimes {
+    /// Return the dwell times with `duration` more spent in `state`.
+    pub(crate) fn with(mut self, state: State, duration: Duration) -> Self {
+        let total = match state {
+            State::Closed => &mut self.closed,
+            State::Open { .. } | State::ForcedOpen => &mut self.open,
+            State::HalfOpen => &mut self.half_open,
+        };
+        *total = total.saturating_add(duration);
+        self
+    }
+}
+
+/// The state and counters of a circuit breaker, e.g. to persist them across
+/// restarts.
+///
+/// An open state carries the remaining trip timeout rather than an instant, so
+/// a snapshot can be restored relative to another clock. The outcomes in a
+/// sliding window are not part of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateSnapshot {
+    /// The state when the snapshot was taken.
+    pub state: State,
+    /// The number of consecutive failures in the closed state.
+    pub consecutive_failures: usize,
+    /// The number of consecutive successes in the half-open state.
+    pub consecutive_successes: usize,
+    /// The number of times the circuit breaker opened since it was last closed.
+    pub reopens: u32,
+}
+
+/// A change of a `StateMachine`'s state, as passed to its state change callback.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateChange {
+    /// The name of the circuit breaker, if it was given one.
+    pub name: Option<Arc<str>>,
+    /// The state before the change.
+    pub from: State,
+    /// The state after the change.
+    pub to: State,
+}
+
+/// An event of a `StateMachine`, as sent to its subscribers.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakerEvent {
+    /// A call succeeded after `elapsed`.
+    Success { elapsed: Duration },
+    /// A call failed after `elapsed`.
+    Failure { elapsed: Duration },
+    /// A call was rejected.
+    Rejected,
+    /// The state changed.
+    StateChange(StateChange),
+}
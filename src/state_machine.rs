@@ -0,0 +1,3372 @@
+use std::any::{self, Any};
+use std::convert::Infallible;
+#[cfg(feature = "async")]
+use std::future::Future;
+#[cfg(feature = "async")]
+use std::mem;
+use std::panic::{self, UnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Weak};
+#[cfg(feature = "async")]
+use std::task::Waker;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "hdrhistogram")]
+use hdrhistogram::Histogram;
+
+use crate::builder::{StateMachineBuilder, TripPredicate};
+use crate::call_outcome::{CallOutcome, TraceInfo};
+use crate::circuit_breaker::CircuitBreaker;
+use crate::clock::MonotonicClock;
+#[cfg(feature = "async")]
+use crate::closed::Closed;
+use crate::config::{BatchFailure, Config, ConfigError};
+use crate::error::Error;
+use crate::machine::Machine;
+use crate::metrics::{self, Counters, Metrics};
+use crate::permit::Permit;
+use crate::rng::Rng;
+use crate::state::{
+    BreakerEvent, DwellTimes, State, StateChange, StateLabel, StateSnapshot, TransitionEvent,
+    TripReason,
+};
+use crate::sync::{Condvar, Mutex, RwLock};
+use crate::window::{Outcome, WindowStats};
+
+impl CircuitBreaker for StateMachine {
+    fn is_call_permitted(&self) -> bool {
+        self.is_call_permitted()
+    }
+
+    fn call_weighted<W, F, T, E>(&self, weigh: W, f: F) -> Result<T, Error<E>>
+    where
+        W: Fn(&E) -> usize,
+        F: FnOnce() -> Result<T, E>,
+        T: 'static,
+        E: 'static,
+    {
+        self.run(
+            || f().map_err(Error::Inner),
+            |result| result.map_or_else(weigh, |_| 0),
+        )
+    }
+}
+
+// Count every error of a call as one failure and every value as success, as
+// the `classify` of `StateMachine::run`.
+pub(crate) fn every_error<T, E>(result: Result<&T, &E>) -> usize {
+    usize::from(result.is_err())
+}
+
+// The discriminants of `State` mirrored in `Inner::status`.
+const STATUS_CLOSED: u8 = 0;
+const STATUS_OPEN: u8 = 1;
+const STATUS_HALF_OPEN: u8 = 2;
+const STATUS_FORCED_OPEN: u8 = 3;
+
+fn tag(state: &State) -> u8 {
+    match state {
+        State::Closed => STATUS_CLOSED,
+        State::Open { .. } => STATUS_OPEN,
+        State::HalfOpen => STATUS_HALF_OPEN,
+        State::ForcedOpen => STATUS_FORCED_OPEN,
+    }
+}
+
+// The number of events a subscriber can lag behind before newer ones are dropped.
+const SUBSCRIBER_CAPACITY: usize = 256;
+
+type StateChangeCallback = Arc<dyn Fn(&StateChange) + Send + Sync>;
+type RejectedCallback = Arc<dyn Fn() + Send + Sync>;
+type HalfOpenCallback = Arc<dyn Fn() + Send + Sync>;
+type WarnCallback = Arc<dyn Fn(usize) + Send + Sync>;
+// A `SuccessSampler<T>` for the `T` it was registered for.
+type AnySuccessSampler = Arc<dyn Any + Send + Sync>;
+type SuccessSampler<T> = Box<dyn Fn(&T) + Send + Sync>;
+// An `ErrorFormatter<E>` for the `E` it was registered for.
+type AnyErrorFormatter = Arc<dyn Any + Send + Sync>;
+type ErrorFormatter<E> = Box<dyn Fn(&E) -> String + Send + Sync>;
+type HealthCheck = Arc<dyn Fn() -> bool + Send + Sync>;
+
+#[derive(Default)]
+struct Callbacks {
+    on_state_change: Option<StateChangeCallback>,
+    on_rejected: Option<RejectedCallback>,
+    on_half_open: Option<HalfOpenCallback>,
+    on_warn: Option<WarnCallback>,
+    success_sampler: Option<AnySuccessSampler>,
+    error_formatter: Option<AnyErrorFormatter>,
+    // The health check and the interval it is run at while open.
+    health_check: Option<(Duration, HealthCheck)>,
+}
+
+// Tells the background health check to stop, waking it up early.
+#[derive(Default)]
+struct Shutdown {
+    stopped: Mutex<bool>,
+    signal: Condvar,
+}
+
+impl Shutdown {
+    fn stop(&self) {
+        *self.stopped.lock() = true;
+        self.signal.notify_all();
+    }
+
+    fn is_stopped(&self) -> bool {
+        *self.stopped.lock()
+    }
+
+    // Sleep for `duration` unless stopped first, and tell whether it was.
+    fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now().checked_add(duration);
+        let mut stopped = self.stopped.lock();
+        while !*stopped {
+            let timeout = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => duration,
+            };
+            if timeout.is_zero() {
+                break;
+            }
+            stopped = self.signal.wait_timeout(stopped, timeout);
+        }
+        *stopped
+    }
+}
+
+struct Inner {
+    machine: Mutex<Machine>,
+    // Notified whenever the machine closes or goes half-open.
+    permitted: Condvar,
+    // The discriminant of the machine's state, only written while holding the lock,
+    // so the hot path can tell the closed state without taking the lock.
+    status: AtomicU8,
+    // Whether a recovery ramp is configured, which the hot path has to respect.
+    ramp: AtomicBool,
+    // The machine's generation, only written while holding the lock, so the
+    // hot path can tell it to a permit without taking the lock.
+    generation: AtomicU64,
+    callbacks: RwLock<Callbacks>,
+    // Whether a background thread is running the health check.
+    health_checking: AtomicBool,
+    // The background thread running the health check, joined on shutdown.
+    health_check_thread: Mutex<Option<JoinHandle<()>>>,
+    // Shared with the health check thread, which only holds a weak reference
+    // to the rest, so that dropping the last handle stops it.
+    shutdown: Arc<Shutdown>,
+    // The tasks awaiting `closed`, woken whenever the machine closes.
+    #[cfg(feature = "async")]
+    closed_wakers: Mutex<Vec<Waker>>,
+    // The `TripPredicate<E>` set with `trip_only_on`, for the `E` it was registered for.
+    trip_predicate: Option<Box<dyn Any + Send + Sync>>,
+    // The senders of the channels returned by `subscribe`.
+    subscribers: Mutex<Vec<SyncSender<BreakerEvent>>>,
+    // The value of the last successful `call_or_last_good`.
+    last_good: Mutex<Option<Box<dyn Any + Send>>>,
+    // The latencies of all recorded calls, in nanoseconds.
+    #[cfg(feature = "hdrhistogram")]
+    latencies: Mutex<Histogram<u64>>,
+    counters: Counters,
+}
+
+impl Inner {
+    fn shutdown(&self) {
+        self.shutdown.stop();
+        let thread = self.health_check_thread.lock().take();
+        // The last handle may be dropped by the health check thread itself.
+        if let Some(thread) = thread.filter(|thread| thread.thread().id() != thread::current().id())
+        {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// A `CircuitBreaker` implemented as a state machine.
+///
+/// Cloning a `StateMachine` is cheap and returns another handle to the same
+/// circuit breaker: clones observe and update the same state, so one breaker
+/// can be shared across threads without wrapping it in an `Arc`.
+///
+/// Dropping the last handle stops and joins the background thread running the
+/// health check, if any.
+///
+/// Like with a `Permit`, the outcome of a call which completes after the state
+/// changed since it was permitted is only counted in the metrics.
+#[derive(Clone)]
+pub struct StateMachine {
+    inner: Arc<Inner>,
+    clock: Arc<dyn MonotonicClock>,
+    rng: Arc<dyn Rng>,
+    name: Option<Arc<str>>,
+    // The time of the clock the timestamps passed to the machine are relative to.
+    epoch: Duration,
+}
+
+// A handle which does not keep the circuit breaker alive, as held by the
+// health check thread.
+struct WeakStateMachine {
+    inner: Weak<Inner>,
+    clock: Arc<dyn MonotonicClock>,
+    rng: Arc<dyn Rng>,
+    name: Option<Arc<str>>,
+    epoch: Duration,
+}
+
+impl WeakStateMachine {
+    fn new(breaker: &StateMachine) -> Self {
+        WeakStateMachine {
+            inner: Arc::downgrade(&breaker.inner),
+            clock: breaker.clock.clone(),
+            rng: breaker.rng.clone(),
+            name: breaker.name.clone(),
+            epoch: breaker.epoch,
+        }
+    }
+
+    fn upgrade(&self) -> Option<StateMachine> {
+        Some(StateMachine {
+            inner: self.inner.upgrade()?,
+            clock: self.clock.clone(),
+            rng: self.rng.clone(),
+            name: self.name.clone(),
+            epoch: self.epoch,
+        })
+    }
+}
+
+/// Build a closed `StateMachine` with the defaults of the `StateMachineBuilder`.
+impl Default for StateMachine {
+    fn default() -> Self {
+        Self::builder().build_unchecked()
+    }
+}
+
+impl StateMachine {
+    /// Create a `StateMachine` tripping after `max_failures` consecutive
+    /// failures and staying open for `trip_timeout`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_failures` is 0. Use `builder` to handle an invalid
+    /// threshold instead.
+    pub fn new(max_failures: usize, trip_timeout: Duration) -> Self {
+        Self::builder()
+            .max_failures(max_failures)
+            .trip_timeout(trip_timeout)
+            .build()
+            .unwrap_or_else(|err| panic!("invalid circuit breaker config: {err}"))
+    }
+
+    pub(crate) fn from_builder(builder: StateMachineBuilder) -> Self {
+        let ramp = builder.config.recovery_ramp.is_some();
+        let mut machine = Machine::new(builder.config, Duration::ZERO);
+        let rng = builder.rng.clone();
+        machine.set_jitter_source(move || rng.next_f64());
+        if let Some(policy) = builder.policy {
+            machine.set_policy(policy);
+        }
+        if let Some(capacity) = builder.history_capacity {
+            machine.set_history_capacity(capacity);
+        }
+        let status = tag(&machine.state(Duration::ZERO));
+        StateMachine {
+            inner: Arc::new(Inner {
+                machine: Mutex::new(machine),
+                permitted: Condvar::new(),
+                status: AtomicU8::new(status),
+                ramp: AtomicBool::new(ramp),
+                generation: AtomicU64::new(0),
+                callbacks: RwLock::new(Callbacks::default()),
+                health_checking: AtomicBool::new(false),
+                health_check_thread: Mutex::new(None),
+                shutdown: Arc::new(Shutdown::default()),
+                #[cfg(feature = "async")]
+                closed_wakers: Mutex::new(Vec::new()),
+                trip_predicate: builder.trip_predicate,
+                subscribers: Mutex::new(Vec::new()),
+                last_good: Mutex::new(None),
+                #[cfg(feature = "hdrhistogram")]
+                latencies: Mutex::new(
+                    Histogram::new(3).expect("3 significant figures are supported"),
+                ),
+                counters: Counters::default(),
+            }),
+            epoch: builder.clock.now(),
+            clock: builder.clock,
+            rng: builder.rng,
+            name: builder.name.map(Arc::from),
+        }
+    }
+
+    /// Create a `StateMachine` with the thresholds and timeouts in `config`.
+    ///
+    /// Return:
+    ///     the `StateMachine` if `config` is valid.
+    ///     the first problem `Config::validate` finds otherwise.
+    pub fn from_config(config: Config) -> Result<Self, ConfigError> {
+        StateMachineBuilder::from_config(config).build()
+    }
+
+    /// Start configuring a `StateMachine` with a `StateMachineBuilder`.
+    pub fn builder() -> StateMachineBuilder {
+        StateMachineBuilder::new()
+    }
+
+    /// Return the name given to the circuit breaker, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Return a copy of the thresholds and timeouts in effect, including any
+    /// runtime reconfiguration, e.g. to echo them on an admin endpoint.
+    pub fn config(&self) -> Config {
+        self.inner.machine.lock().config().clone()
+    }
+
+    /// Swap the thresholds and timeouts for `config` at runtime, keeping the
+    /// current state and counters.
+    ///
+    /// Consecutive failures beyond the new `max_failures` are clamped to it, so
+    /// the next failure trips the circuit breaker. An open circuit breaker
+    /// keeps its current trip timeout. The sliding window is only rebuilt,
+    /// losing its outcomes, if its kind or size changes.
+    ///
+    /// Return:
+    ///     `Ok(())` if `config` is valid and in effect.
+    ///     the first problem `Config::validate` finds otherwise, in which case
+    ///     the current config is kept.
+    pub fn reconfigure(&self, config: Config) -> Result<(), ConfigError> {
+        config.validate()?;
+        let now = self.timestamp();
+        self.with_machine(|machine| {
+            // Written under the lock, so racing reconfigurations cannot leave
+            // the hot path disagreeing with the machine.
+            self.inner
+                .ramp
+                .store(config.recovery_ramp.is_some(), Ordering::Release);
+            machine.reconfigure(config, now);
+        });
+        Ok(())
+    }
+
+    /// Register a callback invoked with the old and the new state and the name
+    /// of the circuit breaker whenever it changes its state.
+    ///
+    /// The callback is invoked after the internal lock is released, so it may
+    /// call back into the circuit breaker. It replaces any previously registered
+    /// callback and is shared by all handles of the same circuit breaker.
+    pub fn set_on_state_change(&self, f: impl Fn(&StateChange) + Send + Sync + 'static) {
+        self.inner.callbacks.write().on_state_change = Some(Arc::new(f));
+    }
+
+    /// Register a callback invoked every time a call is rejected, e.g. to count
+    /// shed load.
+    ///
+    /// Like `set_on_state_change`, the callback is invoked without holding the
+    /// internal lock and is shared by all handles of the same circuit breaker.
+    pub fn set_on_rejected(&self, f: impl Fn() + Send + Sync + 'static) {
+        self.inner.callbacks.write().on_rejected = Some(Arc::new(f));
+    }
+
+    /// Return a channel receiving an event for every success, failure, rejected
+    /// call and state change, e.g. to feed an asynchronous metrics pipeline.
+    ///
+    /// Events are sent without blocking, after the internal lock is released,
+    /// and dropped while the receiver lags 256 events behind. Every subscriber
+    /// receives all events, and dropping the receiver unsubscribes it.
+    pub fn subscribe(&self) -> Receiver<BreakerEvent> {
+        let (sender, receiver) = mpsc::sync_channel(SUBSCRIBER_CAPACITY);
+        self.inner.subscribers.lock().push(sender);
+        receiver
+    }
+
+    /// Register a callback invoked whenever the circuit breaker goes half-open,
+    /// e.g. to warm up a connection pool before the probes.
+    ///
+    /// It is invoked after the `set_on_state_change` callback for the same
+    /// transition, likewise without holding the internal lock.
+    pub fn set_on_half_open(&self, f: impl Fn() + Send + Sync + 'static) {
+        self.inner.callbacks.write().on_half_open = Some(Arc::new(f));
+    }
+
+    /// Open the circuit breaker for the duration `f` returns when given the
+    /// number of times it reopened since it last closed, 0 on the first trip,
+    /// e.g. to wait longer at night, rather than for the backoff of
+    /// `trip_timeout`.
+    ///
+    /// With `BackoffOnHalfOpenFailure::Repeat` a failed probe does not count
+    /// as a reopen. The jitter still applies. An open circuit breaker keeps its
+    /// current trip timeout.
+    pub fn set_timeout_fn(&self, f: impl Fn(u32) -> Duration + Send + Sync + 'static) {
+        self.inner.machine.lock().set_timeout_fn(f);
+    }
+
+    /// Register a callback invoked with the number of consecutive failures
+    /// when they reach the warn threshold, e.g. to alert before the circuit
+    /// breaker trips.
+    ///
+    /// It is invoked once per crossing, without holding the internal lock.
+    pub fn set_on_warn(&self, f: impl Fn(usize) + Send + Sync + 'static) {
+        self.inner.callbacks.write().on_warn = Some(Arc::new(f));
+    }
+
+    /// Register a callback peeking at the values returned by successful calls,
+    /// e.g. to log a sample of responses for debugging without cloning them.
+    ///
+    /// Only calls returning exactly the registered `T` are sampled, e.g. a
+    /// sampler for `String` never sees the value of a call returning
+    /// `&'static str`. Slow calls are not sampled either, as they are recorded
+    /// as unhealthy.
+    pub fn set_success_sampler<T: 'static>(&self, f: impl Fn(&T) + Send + Sync + 'static) {
+        let sampler: SuccessSampler<T> = Box::new(f);
+        self.inner.callbacks.write().success_sampler = Some(Arc::new(sampler));
+    }
+
+    /// Register a function describing the errors of type `E` failed calls
+    /// return, e.g. with `ToString::to_string`, to keep as `last_error`.
+    ///
+    /// Without one for the error type of a call, its failure is described by
+    /// the name of the type.
+    pub fn set_error_formatter<E: 'static>(
+        &self,
+        f: impl Fn(&E) -> String + Send + Sync + 'static,
+    ) {
+        let formatter: ErrorFormatter<E> = Box::new(f);
+        self.inner.callbacks.write().error_formatter = Some(Arc::new(formatter));
+    }
+
+    /// Register a health check run every `interval` while the circuit breaker
+    /// is open, moving it to half-open as soon as the check passes rather than
+    /// waiting for the trip timeout to expire.
+    ///
+    /// The check runs on a background thread which stops once the circuit
+    /// breaker is no longer open.
+    pub fn set_health_check(
+        &self,
+        interval: Duration,
+        check: impl Fn() -> bool + Send + Sync + 'static,
+    ) {
+        self.inner.callbacks.write().health_check = Some((interval, Arc::new(check)));
+        if self.inner.status.load(Ordering::Acquire) == STATUS_OPEN {
+            self.spawn_health_check();
+        }
+    }
+
+    /// Run `f` under the lock and report the transitions it made once the lock
+    /// is released.
+    fn with_machine<R>(&self, f: impl FnOnce(&mut Machine) -> R) -> R {
+        self.with_machine_reported(f, |_| {})
+    }
+
+    /// Run `f` under the lock like `with_machine`, then `report` its result
+    /// once the lock is released, before the transitions.
+    fn with_machine_reported<R>(
+        &self,
+        f: impl FnOnce(&mut Machine) -> R,
+        report: impl FnOnce(&R),
+    ) -> R {
+        let (result, transitions, warning) = {
+            let mut machine = self.inner.machine.lock();
+            let result = f(&mut machine);
+            let transitions = machine.take_transitions();
+            let warning = machine.take_warning();
+            if let Some((_, to)) = transitions.last() {
+                self.inner.status.store(tag(to), Ordering::Release);
+                self.inner
+                    .generation
+                    .store(machine.generation(), Ordering::Release);
+            }
+            (result, transitions, warning)
+        };
+        report(&result);
+
+        if let Some(failures) = warning {
+            let on_warn = self.inner.callbacks.read().on_warn.clone();
+            if let Some(on_warn) = on_warn {
+                on_warn(failures);
+            }
+        }
+
+        if !transitions.is_empty() {
+            Counters::add(
+                &self.inner.counters.state_transitions,
+                transitions.len() as u64,
+            );
+            if let Some((_, to)) = transitions.last() {
+                metrics::export_state(self.name(), tag(to));
+            }
+            let opened = transitions
+                .iter()
+                .any(|(_, to)| matches!(to, State::Open { .. }));
+            if transitions
+                .iter()
+                .any(|(_, to)| matches!(to, State::Closed | State::HalfOpen))
+            {
+                self.inner.permitted.notify_all();
+            }
+            #[cfg(feature = "async")]
+            if transitions.iter().any(|(_, to)| *to == State::Closed) {
+                let wakers = mem::take(&mut *self.inner.closed_wakers.lock());
+                wakers.into_iter().for_each(Waker::wake);
+            }
+            let (on_state_change, on_half_open) = {
+                let callbacks = self.inner.callbacks.read();
+                (
+                    callbacks.on_state_change.clone(),
+                    callbacks.on_half_open.clone(),
+                )
+            };
+            for (from, to) in transitions {
+                let change = StateChange {
+                    name: self.name.clone(),
+                    from,
+                    to,
+                };
+                if let Some(on_state_change) = on_state_change.as_ref() {
+                    on_state_change(&change);
+                }
+                self.publish(|| BreakerEvent::StateChange(change));
+                if let (State::HalfOpen, Some(on_half_open)) = (to, on_half_open.as_ref()) {
+                    on_half_open();
+                }
+            }
+            if opened {
+                self.spawn_health_check();
+            }
+        }
+
+        result
+    }
+
+    /// Ask permission to call.
+    ///
+    /// In the half-open state a permitted call takes one of the probe slots
+    /// limited by `half_open_max_calls`, which is given back once the outcome
+    /// of the call is recorded, or only after the `probe_timeout` if none is.
+    /// Use `would_probe` or `current_state` to merely query the circuit
+    /// breaker. During a recovery ramp after closing, a call is only permitted
+    /// with the ramp's current probability.
+    ///
+    /// In the closed state without a recovery ramp, the permission is granted
+    /// without taking the lock.
+    pub fn is_call_permitted(&self) -> bool {
+        self.permit_generation().is_some()
+    }
+
+    // Ask permission to call, returning the machine's generation it was given
+    // at.
+    fn permit_generation(&self) -> Option<u64> {
+        // The status is stored before the generation, so reading them the other
+        // way round can only pair the status with an older generation, making
+        // a permit stale rather than wrongly current.
+        let generation = self.inner.generation.load(Ordering::Acquire);
+        if !self.inner.ramp.load(Ordering::Acquire)
+            && self.inner.status.load(Ordering::Acquire) == STATUS_CLOSED
+        {
+            return Some(generation);
+        }
+
+        let now = self.timestamp();
+        self.with_machine(|machine| {
+            machine
+                .is_call_permitted(now, || self.rng.next_f64())
+                .then(|| machine.generation())
+        })
+    }
+
+    /// Block the current thread until a call would be permitted, or at most
+    /// for `max_wait`, e.g. for a low-priority job which would rather wait than
+    /// be rejected.
+    ///
+    /// The thread is woken when the circuit breaker closes or goes half-open, or
+    /// when its trip timeout expires. No permission is taken, so a call made
+    /// afterwards may still be rejected if other calls take it first.
+    ///
+    /// Return:
+    ///     `true` if a call would be permitted.
+    ///     `false` if `max_wait` elapsed first.
+    pub fn wait_until_permitted(&self, max_wait: Duration) -> bool {
+        let deadline = Instant::now().checked_add(max_wait);
+        let mut machine = self.inner.machine.lock();
+        loop {
+            let now = self.timestamp();
+            if machine.would_permit(now) {
+                return true;
+            }
+
+            let mut timeout = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => max_wait,
+            };
+            if timeout.is_zero() {
+                return false;
+            }
+            if let Some(remaining) = machine.retry_after(now) {
+                // Wake up once the trip timeout expires.
+                timeout = timeout.min(remaining);
+            }
+            machine = self.inner.permitted.wait_timeout(machine, timeout);
+        }
+    }
+
+    /// Ask permission to call and return a `Permit` to record the outcome with.
+    ///
+    /// Return:
+    ///     `Some(permit)` if a call is allowed.
+    ///     `None` if a call is prohibited.
+    pub fn acquire(&self) -> Option<Permit<'_>> {
+        let generation = self.acquire_generation()?;
+        Some(Permit::new(self, self.timestamp(), generation))
+    }
+
+    /// Record a call which took `elapsed` as success, e.g. from existing
+    /// instrumentation which already knows the outcome.
+    ///
+    /// Every recorded call must have been permitted by `is_call_permitted`,
+    /// as recording gives back the probe slot it took in the half-open state.
+    /// `acquire` keeps track of this with a `Permit` instead.
+    pub fn record_success(&self, elapsed: Duration) {
+        self.on_success(elapsed);
+    }
+
+    /// Record a call which took `elapsed` as failure, e.g. from existing
+    /// instrumentation which already knows the outcome.
+    ///
+    /// Like with `record_success`, the call must have been permitted by
+    /// `is_call_permitted`.
+    pub fn record_failure(&self, elapsed: Duration) {
+        self.on_error(elapsed, 1);
+    }
+
+    /// Call a given future-returning function within Circuit Breaker.
+    ///
+    /// The call will be recorded as success or failure once the future resolves.
+    /// No lock is held while the future is awaited.
+    #[cfg(feature = "async")]
+    pub async fn call_async<F, Fut, T, E>(&self, f: F) -> Result<T, Error<E>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        T: 'static,
+        E: 'static,
+    {
+        let Some(permit) = self.acquire() else {
+            return Err(self.rejected());
+        };
+
+        let result = f().await.map_err(Error::Inner);
+        let (generation, elapsed) = permit.finish();
+        self.record(generation, elapsed, &result, every_error);
+        result
+    }
+
+    /// Call a given function on a worker thread, giving up on it if it does not
+    /// complete within `timeout`.
+    ///
+    /// A call which times out is recorded as failure and returns
+    /// `Error::Timeout`. The abandoned work is not cancelled and may still run
+    /// in the background. A panic in `f` is propagated to the caller.
+    pub fn call_timeout<F, T, E>(&self, timeout: Duration, f: F) -> Result<T, Error<E>>
+    where
+        F: FnOnce() -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: Send + 'static,
+    {
+        // The panic of the worker, resumed once it is recorded as failure.
+        let mut panicked = None;
+        let result = self.run(
+            || {
+                let (sender, receiver) = mpsc::sync_channel(1);
+                let worker = thread::spawn(move || {
+                    let _ = sender.send(f());
+                });
+                match receiver.recv_timeout(timeout) {
+                    Ok(result) => result.map_err(Error::Inner),
+                    Err(RecvTimeoutError::Timeout) => Err(Error::Timeout),
+                    Err(RecvTimeoutError::Disconnected) => {
+                        match worker.join() {
+                            Err(payload) => panicked = Some(payload),
+                            Ok(()) => unreachable!("the worker sends a result unless it panics"),
+                        }
+                        Err(Error::Panic)
+                    }
+                }
+            },
+            every_error,
+        );
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+        result
+    }
+
+    /// Call a given future-returning function within Circuit Breaker, giving up
+    /// on the future if it does not resolve within `timeout`.
+    ///
+    /// A call which times out is recorded as failure and returns
+    /// `Error::Timeout`. The future is dropped, so unlike with `call_timeout`
+    /// no work is left running. It must be awaited within a Tokio runtime with
+    /// the time driver enabled.
+    #[cfg(feature = "tokio")]
+    pub async fn call_async_timeout<F, Fut, T, E>(
+        &self,
+        timeout: Duration,
+        f: F,
+    ) -> Result<T, Error<E>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        T: 'static,
+        E: 'static,
+    {
+        let Some(permit) = self.acquire() else {
+            return Err(self.rejected());
+        };
+
+        let result = match ::tokio::time::timeout(timeout, f()).await {
+            Ok(result) => result.map_err(Error::Inner),
+            Err(_) => Err(Error::Timeout),
+        };
+        let (generation, elapsed) = permit.finish();
+        self.record(generation, elapsed, &result, every_error);
+        result
+    }
+
+    /// Wait until the circuit breaker is closed, resolving at once if it
+    /// already is.
+    #[cfg(feature = "async")]
+    pub fn closed(&self) -> Closed<'_> {
+        Closed::new(self)
+    }
+
+    /// Call a given function within Circuit Breaker, recording a panic in `f`
+    /// as failure and returning `Error::Panic` instead of unwinding.
+    ///
+    /// The panic payload is dropped. `f` must be `UnwindSafe`, which e.g. a
+    /// closure capturing a `&mut` or a `RefCell` is not, as it could leave
+    /// them broken halfway through an update. Wrap it in `AssertUnwindSafe`
+    /// if the captured state is not observed after a panic.
+    pub fn call_catch_unwind<F, T, E>(&self, f: F) -> Result<T, Error<E>>
+    where
+        F: FnOnce() -> Result<T, E> + UnwindSafe,
+        T: 'static,
+        E: 'static,
+    {
+        self.run(
+            || match panic::catch_unwind(f) {
+                Ok(result) => result.map_err(Error::Inner),
+                Err(_) => Err(Error::Panic),
+            },
+            every_error,
+        )
+    }
+
+    /// Call a given function within Circuit Breaker, telling apart why a call
+    /// was rejected, e.g. to answer with a `Retry-After` header.
+    pub fn call_detailed<F, T, E>(&self, f: F) -> CallOutcome<T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+        T: 'static,
+        E: 'static,
+    {
+        match self.call(f) {
+            Ok(ok) => CallOutcome::Success(ok),
+            Err(Error::Inner(err)) => CallOutcome::Failure(err),
+            Err(Error::Rejected {
+                state: StateLabel::Open | StateLabel::ForcedOpen,
+                retry_after,
+            }) => CallOutcome::RejectedOpen {
+                retry_after: retry_after.unwrap_or(Duration::MAX),
+            },
+            Err(Error::Rejected {
+                state: StateLabel::HalfOpen,
+                ..
+            }) => CallOutcome::RejectedHalfOpenBusy,
+            Err(Error::Rejected {
+                state: StateLabel::Closed,
+                ..
+            }) => CallOutcome::RejectedThrottled,
+            Err(Error::Timeout | Error::Panic) => {
+                unreachable!("`call` neither times out nor catches panics")
+            }
+        }
+    }
+
+    /// Call a given function within Circuit Breaker, returning along with its
+    /// result the states before and after the call and whether it was a probe.
+    ///
+    /// Unlike `call`, the lock is always taken to ask for permission, so that
+    /// the state before is the one the permission was decided in.
+    pub fn call_traced<F, T, E>(&self, f: F) -> (Result<T, Error<E>>, TraceInfo)
+    where
+        F: FnOnce() -> Result<T, E>,
+        T: 'static,
+        E: 'static,
+    {
+        Counters::increment(&self.inner.counters.total_calls);
+        let now = self.timestamp();
+        let (before, generation, probe) = self.with_machine(|machine| {
+            let before = machine.state(now);
+            let generation = machine
+                .is_call_permitted(now, || self.rng.next_f64())
+                .then(|| machine.generation());
+            let probe = generation.is_some() && machine.state(now) == State::HalfOpen;
+            (before, generation, probe)
+        });
+        let Some(generation) = generation else {
+            self.on_rejected();
+            let trace = TraceInfo {
+                before,
+                probe,
+                after: self.current_state(),
+            };
+            return (Err(self.rejected()), trace);
+        };
+
+        let permit = Permit::new(self, self.timestamp(), generation);
+        let result = self.run_permitted(permit, || f().map_err(Error::Inner), every_error);
+        let trace = TraceInfo {
+            before,
+            probe,
+            after: self.current_state(),
+        };
+        (result, trace)
+    }
+
+    /// Tell whether `err` counts as failure under the predicate set with
+    /// `trip_only_on`.
+    ///
+    /// Without a predicate, or with one for another error type, every error
+    /// counts as failure.
+    pub fn trips_on<E: 'static>(&self, err: &E) -> bool {
+        self.inner
+            .trip_predicate
+            .as_ref()
+            .and_then(|predicate| predicate.downcast_ref::<TripPredicate<E>>())
+            .is_none_or(|predicate| predicate(err))
+    }
+
+    /// Call a given function flushing a batch of `n` operations within Circuit
+    /// Breaker, asking permission and taking the lock once for the whole batch.
+    ///
+    /// A rejection rejects the whole batch. Success is recorded as `n`
+    /// successes, and failure as one or `n` failures depending on the
+    /// configured `BatchFailure`. The cumulative metrics count the batch as one
+    /// call.
+    pub fn call_batch<F, T, E>(&self, n: u32, f: F) -> Result<T, Error<E>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        E: 'static,
+    {
+        let Some(permit) = self.acquire() else {
+            return Err(self.rejected());
+        };
+
+        let result = f();
+        let (generation, elapsed) = permit.finish();
+        let message = result
+            .as_ref()
+            .err()
+            .filter(|err| self.trips_on(*err))
+            .map(|err| self.describe(err));
+        self.record_batch(generation, elapsed, n, message);
+        result.map_err(Error::Inner)
+    }
+
+    /// Call a given function which cannot fail within Circuit Breaker,
+    /// recording every call as success.
+    ///
+    /// The call is still rejected while the circuit breaker is open, and a
+    /// slow call still counts as unhealthy.
+    pub fn call_infallible<F, T>(&self, f: F) -> Result<T, Error<Infallible>>
+    where
+        F: FnOnce() -> T,
+        T: 'static,
+    {
+        self.call(|| Ok(f()))
+    }
+
+    /// Call a given function within Circuit Breaker, keeping a clone of its
+    /// value on success and returning the kept value instead of
+    /// `Error::Rejected` when the call is rejected, e.g. for idempotent reads.
+    ///
+    /// A single value is kept per circuit breaker, so one kept by a call
+    /// returning another `T` is not returned. It is dropped on `reset`.
+    pub fn call_or_last_good<F, T, E>(&self, f: F) -> Result<T, Error<E>>
+    where
+        F: FnOnce() -> Result<T, E>,
+        T: Clone + Send + 'static,
+        E: 'static,
+    {
+        match self.call(f) {
+            Ok(ok) => {
+                *self.inner.last_good.lock() = Some(Box::new(ok.clone()));
+                Ok(ok)
+            }
+            Err(rejected @ Error::Rejected { .. }) => self
+                .inner
+                .last_good
+                .lock()
+                .as_ref()
+                .and_then(|last_good| last_good.downcast_ref::<T>())
+                .cloned()
+                .ok_or(rejected),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Force the circuit breaker open for the configured trip timeout,
+    /// regardless of its current state, unless it is isolated, which it stays.
+    pub fn trip(&self) {
+        let now = self.timestamp();
+        self.with_machine(|machine| machine.trip(now))
+    }
+
+    /// Isolate the circuit breaker, blocking all calls until it is reset.
+    ///
+    /// Unlike `trip`, an isolated circuit breaker never goes half-open on its
+    /// own, e.g. for maintenance windows.
+    pub fn isolate(&self) {
+        let now = self.timestamp();
+        self.with_machine(|machine| machine.isolate(now))
+    }
+
+    /// Force the circuit breaker closed and clear its counters, regardless of
+    /// its current state. This also ends an isolation and drops the value
+    /// kept by `call_or_last_good`.
+    pub fn reset(&self) {
+        *self.inner.last_good.lock() = None;
+        let now = self.timestamp();
+        self.with_machine(|machine| machine.reset(now))
+    }
+
+    /// Make the transitions due by now without asking permission to call, e.g.
+    /// from a monitoring thread which never calls, firing their callbacks.
+    /// An open circuit breaker otherwise only goes half-open when a call asks
+    /// for permission.
+    ///
+    /// Return:
+    ///     `true` if the state changed.
+    ///     `false` otherwise.
+    pub fn tick(&self) -> bool {
+        let now = self.timestamp();
+        self.with_machine(|machine| machine.tick(now))
+    }
+
+    /// Return a snapshot of the current state.
+    ///
+    /// For an open circuit breaker the snapshot carries the remaining time until
+    /// it goes half-open, which is zero when the trip timeout has already expired
+    /// but no call has asked for permission yet.
+    pub fn current_state(&self) -> State {
+        let now = self.timestamp();
+        self.inner.machine.lock().state(now)
+    }
+
+    /// Tell whether the circuit breaker is open or isolated.
+    ///
+    /// An open circuit breaker whose trip timeout has expired is still open
+    /// until a call asks for permission and makes it go half-open.
+    pub fn is_open(&self) -> bool {
+        matches!(self.current_state(), State::Open { .. } | State::ForcedOpen)
+    }
+
+    /// Tell whether the circuit breaker is closed.
+    pub fn is_closed(&self) -> bool {
+        self.current_state() == State::Closed
+    }
+
+    /// Tell whether the circuit breaker is half-open.
+    pub fn is_half_open(&self) -> bool {
+        self.current_state() == State::HalfOpen
+    }
+
+    /// Return how long the circuit breaker has been in its current state, e.g.
+    /// to alert on a circuit breaker which stays open.
+    pub fn time_in_current_state(&self) -> Duration {
+        let now = self.timestamp();
+        self.inner.machine.lock().time_in_current_state(now)
+    }
+
+    /// Return a snapshot of the state and counters, e.g. to persist them across
+    /// restarts.
+    pub fn export_state(&self) -> StateSnapshot {
+        let now = self.timestamp();
+        self.inner.machine.lock().export_state(now)
+    }
+
+    /// Restore the state and counters from `snapshot`, e.g. after a restart.
+    ///
+    /// An open state stays open for its remaining trip timeout from now on.
+    /// Changing the state this way is reported like any other transition.
+    pub fn import_state(&self, snapshot: StateSnapshot) {
+        let now = self.timestamp();
+        self.with_machine(|machine| machine.import_state(snapshot, now))
+    }
+
+    /// Return the number of consecutive failures recorded in the closed state
+    /// since the last success or since the circuit breaker last closed.
+    ///
+    /// A weighted failure adds its weight and a slow call counts as a failure.
+    pub fn consecutive_failures(&self) -> usize {
+        self.inner.machine.lock().consecutive_failures()
+    }
+
+    /// Return the moving average of the failure rate since the circuit breaker
+    /// last closed, or `None` unless it trips on one.
+    pub fn ewma_failure_rate(&self) -> Option<f64> {
+        self.inner.machine.lock().ewma_failure_rate()
+    }
+
+    /// Return the outcomes buffered by a count-based sliding window, oldest
+    /// first, e.g. to see why the circuit breaker tripped.
+    ///
+    /// Their timestamps are relative to when the circuit breaker was built.
+    /// There are none without a sliding window or with a time-based one,
+    /// which only keeps aggregates: use `recent_buckets` for those.
+    pub fn recent_outcomes(&self) -> Vec<Outcome> {
+        self.inner.machine.lock().recent_outcomes()
+    }
+
+    /// Return the aggregated outcomes of each bucket of a time-based sliding
+    /// window holding any, oldest first, along with when the bucket starts.
+    ///
+    /// Like the timestamps of `recent_outcomes`, the starts are relative to
+    /// when the circuit breaker was built. There are none without a sliding
+    /// window or with a count-based one.
+    pub fn recent_buckets(&self) -> Vec<(Duration, WindowStats)> {
+        let now = self.timestamp();
+        self.inner.machine.lock().recent_buckets(now)
+    }
+
+    /// Return the total time spent in each state since the circuit breaker was
+    /// built, including the time spent in the current one so far, e.g. for
+    /// SLA reporting.
+    pub fn dwell_times(&self) -> DwellTimes {
+        let now = self.timestamp();
+        self.inner.machine.lock().dwell_times(now)
+    }
+
+    /// Return the most recent transitions, oldest first, as many as the
+    /// history capacity, e.g. for a post-incident analysis.
+    ///
+    /// Their timestamps are relative to when the circuit breaker was built.
+    pub fn transition_history(&self) -> Vec<TransitionEvent> {
+        self.inner.machine.lock().transition_history()
+    }
+
+    /// Return the latency below which `percentile` percent of the recorded
+    /// calls completed, e.g. 99.0 for the p99, or `None` if no call was
+    /// recorded yet.
+    ///
+    /// Latencies are tracked to 3 significant figures over the lifetime of the
+    /// circuit breaker, whether the calls succeeded or failed.
+    #[cfg(feature = "hdrhistogram")]
+    pub fn latency_percentile(&self, percentile: f64) -> Option<Duration> {
+        let latencies = self.inner.latencies.lock();
+        if latencies.is_empty() {
+            return None;
+        }
+        Some(Duration::from_nanos(
+            latencies.value_at_percentile(percentile),
+        ))
+    }
+
+    /// Return a snapshot of the cumulative call and transition counters.
+    pub fn metrics(&self) -> Metrics {
+        self.inner.counters.snapshot()
+    }
+
+    /// Return the message of the most recent failure since the circuit breaker
+    /// last closed, e.g. the one which tripped it.
+    ///
+    /// An inner error is described with the formatter set with
+    /// `set_error_formatter`, and a timeout or a panic by what happened. A
+    /// failure recorded with `record_failure` or a `Permit` without an error
+    /// leaves the message as it is.
+    pub fn last_error(&self) -> Option<String> {
+        self.inner.machine.lock().last_error().map(String::from)
+    }
+
+    /// Tell whether the next call would be permitted as a half-open probe, e.g.
+    /// to tag it differently in tracing. Nothing is changed, so another call
+    /// may still take the probe slot first.
+    ///
+    /// Return:
+    ///     `true` if the trip timeout has expired, or if the circuit breaker is
+    ///     half-open with a probe slot available.
+    ///     `false` otherwise.
+    pub fn would_probe(&self) -> bool {
+        let now = self.timestamp();
+        self.inner.machine.lock().would_probe(now)
+    }
+
+    /// Return how long the circuit breaker stays open, e.g. for a `Retry-After`
+    /// header.
+    ///
+    /// Return:
+    ///     `Some(remaining)` if the circuit breaker is open, which is zero when
+    ///     the trip timeout has expired but no call has asked for permission yet.
+    ///     `None` otherwise.
+    pub fn retry_after(&self) -> Option<Duration> {
+        let now = self.timestamp();
+        self.inner.machine.lock().retry_after(now)
+    }
+
+    /// Tell why the circuit breaker opened.
+    ///
+    /// Return:
+    ///     `Some(reason)` if the circuit breaker is open.
+    ///     `None` otherwise.
+    pub fn trip_reason(&self) -> Option<TripReason> {
+        self.inner.machine.lock().trip_reason()
+    }
+
+    // Return the time elapsed since the epoch, as the machine expects it and
+    // as calls are timed from.
+    pub(crate) fn timestamp(&self) -> Duration {
+        self.clock.now().saturating_sub(self.epoch)
+    }
+
+    /// Return how long ago a call started at `start`.
+    pub(crate) fn elapsed(&self, start: Duration) -> Duration {
+        self.timestamp().saturating_sub(start)
+    }
+
+    /// Stop and join the background thread running the health check, if any,
+    /// and run no health check from now on.
+    ///
+    /// This also happens when the last handle is dropped. Workers abandoned by
+    /// `call_timeout` are not joined, as they may never complete.
+    pub fn shutdown(&self) {
+        self.inner.shutdown();
+    }
+
+    // Start the health check thread unless there is no health check or it is
+    // already running.
+    fn spawn_health_check(&self) {
+        let Some((interval, check)) = self.inner.callbacks.read().health_check.clone() else {
+            return;
+        };
+        if self.inner.shutdown.is_stopped()
+            || self.inner.health_checking.swap(true, Ordering::AcqRel)
+        {
+            return;
+        }
+
+        let shutdown = self.inner.shutdown.clone();
+        let weak = WeakStateMachine::new(self);
+        // Hold the lock until the handle is stored, in case the thread spawns
+        // the next one first.
+        let mut slot = self.inner.health_check_thread.lock();
+        let thread = thread::spawn(move || {
+            loop {
+                if shutdown.sleep(interval) {
+                    return;
+                }
+                let Some(breaker) = weak.upgrade() else {
+                    return;
+                };
+                if breaker.inner.status.load(Ordering::Acquire) != STATUS_OPEN {
+                    breaker
+                        .inner
+                        .health_checking
+                        .store(false, Ordering::Release);
+                    // The circuit breaker may have opened again after the last check.
+                    if breaker.inner.status.load(Ordering::Acquire) == STATUS_OPEN {
+                        breaker.spawn_health_check();
+                    }
+                    return;
+                }
+                if check() {
+                    let now = breaker.timestamp();
+                    breaker.with_machine(|machine| machine.half_open(now));
+                }
+            }
+        });
+        *slot = Some(thread);
+    }
+
+    // Send the event made by `event` to every subscriber which is not full,
+    // forgetting those whose receiver was dropped.
+    fn publish(&self, event: impl FnOnce() -> BreakerEvent) {
+        let mut subscribers = self.inner.subscribers.lock();
+        if subscribers.is_empty() {
+            return;
+        }
+        let event = event();
+        subscribers.retain(|subscriber| {
+            !matches!(
+                subscriber.try_send(event.clone()),
+                Err(TrySendError::Disconnected(_))
+            )
+        });
+    }
+
+    // Return the error of a rejected call, from the state at rejection time.
+    pub(crate) fn rejected<E>(&self) -> Error<E> {
+        let now = self.timestamp();
+        let machine = self.inner.machine.lock();
+        Error::Rejected {
+            state: machine.state(now).label(),
+            retry_after: machine.retry_after(now),
+        }
+    }
+
+    pub(crate) fn on_rejected(&self) {
+        Counters::increment(&self.inner.counters.rejected_calls);
+        metrics::export_call(self.name(), "rejected");
+        self.publish(|| BreakerEvent::Rejected);
+        let on_rejected = self.inner.callbacks.read().on_rejected.clone();
+        if let Some(on_rejected) = on_rejected {
+            on_rejected();
+        }
+    }
+
+    // Tell whether the machine is closed, or else register `waker` to be woken
+    // once it closes.
+    #[cfg(feature = "async")]
+    pub(crate) fn register_closed_waker(&self, waker: &Waker) -> bool {
+        if self.inner.status.load(Ordering::Acquire) == STATUS_CLOSED {
+            return true;
+        }
+        let mut wakers = self.inner.closed_wakers.lock();
+        if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+        // Closing in between would have woken the wakers before this one.
+        self.inner.status.load(Ordering::Acquire) == STATUS_CLOSED
+    }
+
+    // Give back a permission given at `generation` which was never used for a
+    // call, unless the state changed since.
+    #[cfg(feature = "tower")]
+    pub(crate) fn release_since(&self, generation: u64) {
+        self.with_machine(|machine| {
+            if generation == machine.generation() {
+                machine.release();
+            }
+        })
+    }
+
+    pub(crate) fn on_error(&self, elapsed: Duration, weight: usize) {
+        self.record_error(elapsed, weight, None, |_| {});
+    }
+
+    // Record a failure of a call permitted at `generation`, which is ignored if
+    // the state changed since.
+    pub(crate) fn on_error_since(&self, generation: u64, elapsed: Duration, weight: usize) {
+        self.record_error(elapsed, weight, Some(generation), |_| {});
+    }
+
+    // Record a success of a call permitted at `generation`, which is ignored if
+    // the state changed since.
+    // Record a failure like `on_error_since`, keeping `message` as the last
+    // error.
+    pub(crate) fn on_error_described(
+        &self,
+        generation: u64,
+        elapsed: Duration,
+        weight: usize,
+        message: String,
+    ) {
+        self.record_error(elapsed, weight, Some(generation), |machine| {
+            machine.set_last_error(message)
+        });
+    }
+
+    pub(crate) fn on_success_since(&self, generation: u64, elapsed: Duration) {
+        self.on_success_slow(elapsed, Some(generation));
+    }
+
+    // Count a call and ask permission for it, counting it as rejected if it
+    // is prohibited, and return the generation it was given at.
+    pub(crate) fn acquire_generation(&self) -> Option<u64> {
+        Counters::increment(&self.inner.counters.total_calls);
+        let generation = self.permit_generation();
+        if generation.is_none() {
+            self.on_rejected();
+        }
+        generation
+    }
+
+    // Call `f` if permitted and record its outcome like `record`. Every
+    // synchronous `call_*` variant goes through here.
+    pub(crate) fn run<F, C, T, E>(&self, f: F, classify: C) -> Result<T, Error<E>>
+    where
+        F: FnOnce() -> Result<T, Error<E>>,
+        C: FnOnce(Result<&T, &E>) -> usize,
+        T: 'static,
+        E: 'static,
+    {
+        let Some(permit) = self.acquire() else {
+            return Err(self.rejected());
+        };
+        self.run_permitted(permit, f, classify)
+    }
+
+    // Call `f` with the permission of `permit` and record its outcome like
+    // `record`.
+    fn run_permitted<F, C, T, E>(
+        &self,
+        permit: Permit<'_>,
+        f: F,
+        classify: C,
+    ) -> Result<T, Error<E>>
+    where
+        F: FnOnce() -> Result<T, Error<E>>,
+        C: FnOnce(Result<&T, &E>) -> usize,
+        T: 'static,
+        E: 'static,
+    {
+        let result = f();
+        let (generation, elapsed) = permit.finish();
+        self.record(generation, elapsed, &result, classify);
+        result
+    }
+
+    // Record the outcome of a call permitted at `generation` which took
+    // `elapsed`, which is ignored if the state changed since. `classify` tells
+    // how many consecutive failures a value or an inner error counts as, 0
+    // recording it as success, while a timeout or a panic counts as one. An
+    // inner error the `trip_only_on` predicate rejects counts as success
+    // whatever `classify` says. A value recorded as a success which was not
+    // slow is handed to the success sampler.
+    pub(crate) fn record<T: 'static, E: 'static>(
+        &self,
+        generation: u64,
+        elapsed: Duration,
+        result: &Result<T, Error<E>>,
+        classify: impl FnOnce(Result<&T, &E>) -> usize,
+    ) {
+        let weight = match result {
+            Ok(ok) => classify(Ok(ok)),
+            Err(Error::Inner(err)) if self.trips_on(err) => classify(Err(err)),
+            Err(Error::Inner(_)) => 0,
+            Err(_) => 1,
+        };
+        match weight {
+            0 => {
+                let slow = self.on_success_slow(elapsed, Some(generation));
+                if let (Ok(ok), false) = (result, slow) {
+                    self.sample(ok);
+                }
+            }
+            weight => {
+                let message = match result {
+                    Ok(_) => String::from("call returned a value counted as failure"),
+                    Err(Error::Inner(err)) => self.describe(err),
+                    Err(Error::Rejected { .. }) => String::from("call rejected by circuit breaker"),
+                    Err(Error::Timeout) => String::from("call timed out"),
+                    Err(Error::Panic) => String::from("call panicked"),
+                };
+                self.on_error_described(generation, elapsed, weight, message);
+            }
+        }
+    }
+
+    // Describe `err` with the error formatter if it was registered for `E`, or
+    // by the name of its type otherwise.
+    pub(crate) fn describe<E: 'static>(&self, err: &E) -> String {
+        let formatter = self.inner.callbacks.read().error_formatter.clone();
+        match formatter
+            .as_ref()
+            .and_then(|formatter| formatter.downcast_ref::<ErrorFormatter<E>>())
+        {
+            Some(format) => format(err),
+            None => String::from(any::type_name::<E>()),
+        }
+    }
+
+    // Hand `value` to the success sampler if it was registered for `T`.
+    fn sample<T: 'static>(&self, value: &T) {
+        let sampler = self.inner.callbacks.read().success_sampler.clone();
+        if let Some(sample) = sampler
+            .as_ref()
+            .and_then(|sampler| sampler.downcast_ref::<SuccessSampler<T>>())
+        {
+            sample(value);
+        }
+    }
+
+    // Record a failure, running `before` on the machine under the same lock.
+    fn record_error(
+        &self,
+        elapsed: Duration,
+        weight: usize,
+        generation: Option<u64>,
+        before: impl FnOnce(&mut Machine),
+    ) {
+        self.count_outcome(false, elapsed);
+        self.record_latency(elapsed);
+        let now = self.timestamp();
+        let half_open = self.with_machine(|machine| {
+            if generation.is_some_and(|generation| generation != machine.generation()) {
+                return false;
+            }
+            let half_open = machine.state(now) == State::HalfOpen;
+            before(machine);
+            machine.on_error(machine.is_slow(elapsed), weight, now);
+            half_open
+        });
+        if half_open {
+            Counters::increment(&self.inner.counters.half_open_failures);
+        }
+    }
+
+    // Record the outcome of a batch of `n` operations permitted at `generation`
+    // under one lock, which is ignored if the state changed since. A failed
+    // batch comes with the message of its error.
+    fn record_batch(&self, generation: u64, elapsed: Duration, n: u32, error: Option<String>) {
+        let success = error.is_none();
+        self.count_outcome(success, elapsed);
+        self.record_latency(elapsed);
+        let now = self.timestamp();
+        let half_open = self.with_machine(|machine| {
+            if generation != machine.generation() {
+                return false;
+            }
+            let half_open = machine.state(now) == State::HalfOpen;
+            let slow = machine.is_slow(elapsed);
+            let outcomes = match machine.config().batch_failure {
+                BatchFailure::Once if !success => 1,
+                // An empty batch still gives back its permission.
+                _ => n.max(1),
+            };
+            if let Some(message) = error {
+                machine.set_last_error(message);
+            }
+            machine.on_batch(success, slow, outcomes as usize, now);
+            half_open
+        });
+        if half_open {
+            let counter = if success {
+                &self.inner.counters.half_open_successes
+            } else {
+                &self.inner.counters.half_open_failures
+            };
+            Counters::increment(counter);
+        }
+    }
+
+    #[cfg(feature = "hdrhistogram")]
+    fn record_latency(&self, elapsed: Duration) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        let mut latencies = self.inner.latencies.lock();
+        // The histogram grows to fit, unless the latency is beyond its range.
+        if latencies.record(nanos).is_err() {
+            latencies.saturating_record(nanos);
+        }
+    }
+
+    #[cfg(not(feature = "hdrhistogram"))]
+    #[inline(always)]
+    fn record_latency(&self, _elapsed: Duration) {}
+
+    pub(crate) fn on_success(&self, elapsed: Duration) {
+        self.on_success_slow(elapsed, None);
+    }
+
+    // Count a completed call as success or failure and publish it.
+    fn count_outcome(&self, success: bool, elapsed: Duration) {
+        let (counter, result) = if success {
+            (&self.inner.counters.successful_calls, "success")
+        } else {
+            (&self.inner.counters.failed_calls, "failure")
+        };
+        Counters::increment(counter);
+        metrics::export_call(self.name(), result);
+        self.publish(|| {
+            if success {
+                BreakerEvent::Success { elapsed }
+            } else {
+                BreakerEvent::Failure { elapsed }
+            }
+        });
+    }
+
+    // Record a success and tell whether the call was slow. A slow probe which
+    // fails with `fail_slow_probes` is counted as failure, which is only known
+    // under the lock.
+    fn on_success_slow(&self, elapsed: Duration, generation: Option<u64>) -> bool {
+        self.record_latency(elapsed);
+        let now = self.timestamp();
+        let (half_open, slow, failed_probe) = self.with_machine_reported(
+            |machine| {
+                if generation.is_some_and(|generation| generation != machine.generation()) {
+                    return (false, false, false);
+                }
+                let half_open = machine.state(now) == State::HalfOpen;
+                let slow = machine.is_slow(elapsed);
+                let failed_probe = half_open && slow && machine.config().fail_slow_probes;
+                machine.on_success(slow, now);
+                (half_open, slow, failed_probe)
+            },
+            |&(_, _, failed_probe)| self.count_outcome(!failed_probe, elapsed),
+        );
+        if failed_probe {
+            Counters::increment(&self.inner.counters.half_open_failures);
+        } else if half_open {
+            Counters::increment(&self.inner.counters.half_open_successes);
+        }
+        slow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::AssertUnwindSafe;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::OnceLock;
+
+    use super::*;
+    use crate::call_outcome::CallOutcome;
+    use crate::clock::TestClock;
+    use crate::config::{BackoffOnHalfOpenFailure, SlidingWindow};
+    use crate::rng::SeededRng;
+
+    #[test]
+    fn stays_closed_until_a_thousand_failures() {
+        let breaker = StateMachine::new(1000, Duration::from_secs(60));
+        for _ in 0..999 {
+            let _ = breaker.call(|| Err::<(), _>(()));
+        }
+        assert!(breaker.is_call_permitted());
+
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert!(!breaker.is_call_permitted());
+    }
+
+    #[test]
+    fn failure_after_two_of_three_successes_reopens() {
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_millis(1))
+            .success_threshold(3)
+            .build()
+            .unwrap();
+        let _ = breaker.call(|| Err::<(), _>(()));
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+        let _ = breaker.call(|| Ok::<_, ()>(()));
+        assert_eq!(breaker.current_state(), State::HalfOpen);
+
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+        // The successes before the failure do not count toward closing.
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+        let _ = breaker.call(|| Ok::<_, ()>(()));
+        assert_eq!(breaker.current_state(), State::HalfOpen);
+        let _ = breaker.call(|| Ok::<_, ()>(()));
+        assert_eq!(breaker.current_state(), State::Closed);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_and_resets_on_close() {
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_millis(20))
+            .max_timeout(Duration::from_millis(80))
+            .backoff_multiplier(2.0)
+            .backoff_on_half_open_failure(BackoffOnHalfOpenFailure::Escalate)
+            .build()
+            .unwrap();
+        let remaining = || match breaker.current_state() {
+            State::Open { remaining, .. } => remaining,
+            state => panic!("expected an open breaker, got {:?}", state),
+        };
+
+        let _ = breaker.call(|| Err::<(), _>(()));
+        for delay in [20, 40, 80, 80] {
+            let delay = Duration::from_millis(delay);
+            let remaining = remaining();
+            assert!(remaining <= delay && remaining > delay / 2);
+            std::thread::sleep(delay);
+            let result = breaker.call(|| Err::<(), _>(()));
+            assert!(matches!(result, Err(Error::Inner(()))));
+        }
+
+        std::thread::sleep(Duration::from_millis(80));
+        let _ = breaker.call(|| Ok::<_, ()>(()));
+        assert_eq!(breaker.current_state(), State::Closed);
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert!(remaining() <= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn state_change_callback_sees_every_transition() {
+        let breaker = StateMachine::new(1, Duration::from_millis(1));
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let seen = changes.clone();
+        breaker.set_on_state_change(move |change| seen.lock().push((change.from, change.to)));
+        let _ = breaker.call(|| Err::<(), _>(()));
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+
+        let changes = changes.lock();
+        assert_eq!(changes.len(), 3);
+        assert!(matches!(changes[0], (State::Closed, State::Open { .. })));
+        assert!(matches!(changes[1], (State::Open { .. }, State::HalfOpen)));
+        assert_eq!(changes[2], (State::HalfOpen, State::Closed));
+    }
+
+    #[test]
+    fn state_change_callback_can_call_back_into_the_breaker() {
+        let breaker = Arc::new(StateMachine::new(1, Duration::from_secs(60)));
+        let handle = Arc::downgrade(&breaker);
+        let observed = Arc::new(Mutex::new(None));
+        let seen = observed.clone();
+        breaker.set_on_state_change(move |_| {
+            *seen.lock() = handle.upgrade().map(|breaker| breaker.current_state());
+        });
+        let _ = breaker.call(|| Err::<(), _>(()));
+
+        assert!(matches!(*observed.lock(), Some(State::Open { .. })));
+    }
+
+    #[test]
+    fn count_window_trips_on_the_failure_rate_of_the_last_calls() {
+        let breaker = StateMachine::builder()
+            .window_size(4)
+            .failure_rate_threshold(0.5)
+            .build()
+            .unwrap();
+        let _ = breaker.call(|| Ok::<_, ()>(()));
+        let _ = breaker.call(|| Ok::<_, ()>(()));
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert_eq!(breaker.current_state(), State::Closed);
+
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn count_window_ignores_consecutive_failures_below_the_rate() {
+        let breaker = StateMachine::builder()
+            .max_failures(2)
+            .window_size(10)
+            .failure_rate_threshold(0.5)
+            .build()
+            .unwrap();
+        for _ in 0..6 {
+            let _ = breaker.call(|| Ok::<_, ()>(()));
+        }
+        for _ in 0..3 {
+            let _ = breaker.call(|| Err::<(), _>(()));
+        }
+        assert_eq!(breaker.current_state(), State::Closed);
+    }
+
+    #[test]
+    fn only_timeouts_count_as_failures() {
+        #[derive(Debug, PartialEq)]
+        enum RequestError {
+            Timeout,
+            NotFound,
+        }
+
+        let breaker = StateMachine::new(2, Duration::from_secs(60));
+        let timeouts = |err: &RequestError| *err == RequestError::Timeout;
+        for _ in 0..5 {
+            let result = breaker.call_with(timeouts, || Err::<(), _>(RequestError::NotFound));
+            assert!(matches!(result, Err(Error::Inner(RequestError::NotFound))));
+        }
+        assert_eq!(breaker.current_state(), State::Closed);
+
+        let _ = breaker.call_with(timeouts, || Err::<(), _>(RequestError::Timeout));
+        assert_eq!(breaker.current_state(), State::Closed);
+        let _ = breaker.call_with(timeouts, || Err::<(), _>(RequestError::Timeout));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn trip_opens_and_reset_closes() {
+        let breaker = StateMachine::builder()
+            .max_failures(3)
+            .trip_timeout(Duration::from_secs(60))
+            .clock(TestClock::new())
+            .build()
+            .unwrap();
+        let changes = Arc::new(AtomicUsize::new(0));
+        let counted = changes.clone();
+        breaker.set_on_state_change(move |_| {
+            counted.fetch_add(1, Ordering::Relaxed);
+        });
+        let _ = breaker.call(|| Err::<(), _>(()));
+
+        breaker.trip();
+        assert_eq!(
+            breaker.current_state(),
+            State::Open {
+                remaining: Duration::from_secs(60),
+                reason: TripReason::Manual,
+            }
+        );
+        assert!(!breaker.is_call_permitted());
+
+        breaker.reset();
+        assert_eq!(breaker.current_state(), State::Closed);
+        assert_eq!(changes.load(Ordering::Relaxed), 2);
+        // The failure before the trip was cleared.
+        let _ = breaker.call(|| Err::<(), _>(()));
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert_eq!(breaker.current_state(), State::Closed);
+    }
+
+    #[test]
+    fn isolated_breaker_never_self_heals() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .trip_timeout(Duration::from_secs(1))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        breaker.isolate();
+        for _ in 0..3 {
+            clock.advance(Duration::from_secs(3600));
+            assert!(!breaker.is_call_permitted());
+            assert_eq!(breaker.current_state(), State::ForcedOpen);
+        }
+
+        breaker.reset();
+        assert_eq!(breaker.current_state(), State::Closed);
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+    }
+
+    #[test]
+    fn retry_after_shrinks_as_time_advances() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .trip_timeout(Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        assert_eq!(breaker.retry_after(), None);
+
+        breaker.trip();
+        assert_eq!(breaker.retry_after(), Some(Duration::from_secs(10)));
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(breaker.retry_after(), Some(Duration::from_secs(6)));
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(breaker.retry_after(), Some(Duration::ZERO));
+
+        assert!(breaker.is_call_permitted());
+        assert_eq!(breaker.retry_after(), None);
+    }
+
+    #[test]
+    fn metrics_count_every_call_and_transition() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(2)
+            .trip_timeout(Duration::from_secs(1))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+        let _ = breaker.call(|| Err::<(), _>(()));
+        let _ = breaker.call(|| Err::<(), _>(()));
+        breaker.isolate();
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_err());
+        breaker.reset();
+        breaker.trip();
+        clock.advance(Duration::from_secs(2));
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+
+        assert_eq!(
+            breaker.metrics(),
+            Metrics {
+                total_calls: 5,
+                successful_calls: 2,
+                failed_calls: 2,
+                rejected_calls: 1,
+                // Closed, open, isolated, closed, open, half-open, closed.
+                state_transitions: 6,
+                half_open_successes: 1,
+                half_open_failures: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn clones_trip_one_shared_breaker() {
+        let breaker = StateMachine::new(8, Duration::from_secs(60));
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let breaker = breaker.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..2 {
+                        let _ = breaker.call(|| Err::<(), _>(()));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+        assert_eq!(breaker.metrics().failed_calls, 8);
+    }
+
+    #[test]
+    fn thresholds_above_the_old_u8_ceiling_work() {
+        let max_failures: usize = 300;
+        let breaker = StateMachine::new(max_failures, Duration::from_secs(60));
+        for _ in 0..299 {
+            let _ = breaker.call(|| Err::<(), _>(()));
+        }
+        assert_eq!(breaker.current_state(), State::Closed);
+
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn recovery_ramp_admits_a_growing_fraction() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .trip_timeout(Duration::from_secs(1))
+            .recovery_ramp(Duration::from_secs(10), 0.2)
+            .clock(clock.clone())
+            .rng(SeededRng::new(42))
+            .build()
+            .unwrap();
+        breaker.trip();
+        clock.advance(Duration::from_secs(2));
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+        assert_eq!(breaker.current_state(), State::Closed);
+
+        let admitted = || (0..1000).filter(|_| breaker.is_call_permitted()).count();
+        let start = admitted();
+        clock.advance(Duration::from_secs(5));
+        let middle = admitted();
+        clock.advance(Duration::from_secs(5));
+        let end = admitted();
+
+        assert!((150..250).contains(&start), "{start}");
+        assert!((550..650).contains(&middle), "{middle}");
+        assert_eq!(end, 1000);
+    }
+
+    #[test]
+    fn rejection_callback_fires_once_per_rejected_call() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        let rejections = Arc::new(AtomicUsize::new(0));
+        let counted = rejections.clone();
+        let handle = breaker.clone();
+        breaker.set_on_rejected(move || {
+            // The callback runs without the lock held.
+            assert!(matches!(handle.current_state(), State::Open { .. }));
+            counted.fetch_add(1, Ordering::Relaxed);
+        });
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert_eq!(rejections.load(Ordering::Relaxed), 0);
+
+        for _ in 0..3 {
+            assert!(breaker.call(|| Ok::<_, ()>(())).is_err());
+        }
+        assert_eq!(rejections.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn a_weighted_failure_trips_at_once() {
+        let breaker = StateMachine::new(3, Duration::from_secs(60));
+        let weigh = |err: &&str| if *err == "timeout" { 3 } else { 1 };
+        let _ = breaker.call_weighted(weigh, || Err::<(), _>("refused"));
+        assert_eq!(breaker.current_state(), State::Closed);
+        breaker.reset();
+
+        let _ = breaker.call_weighted(weigh, || Err::<(), _>("timeout"));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn passing_health_check_goes_half_open() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        let checks = Arc::new(AtomicUsize::new(0));
+        let counted = checks.clone();
+        // Fails twice, then passes.
+        breaker.set_health_check(Duration::from_millis(1), move || {
+            counted.fetch_add(1, Ordering::Relaxed) >= 2
+        });
+        breaker.trip();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while breaker.current_state() != State::HalfOpen && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(breaker.current_state(), State::HalfOpen);
+        assert!(checks.load(Ordering::Relaxed) >= 3);
+
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+        assert_eq!(breaker.current_state(), State::Closed);
+        let stopped_at = checks.load(Ordering::Relaxed);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(checks.load(Ordering::Relaxed), stopped_at);
+    }
+
+    #[test]
+    fn call_completing_in_time_returns_its_result() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        let result = breaker.call_timeout(Duration::from_secs(5), || Ok::<_, ()>(7));
+        assert!(matches!(result, Ok(7)));
+        assert_eq!(breaker.metrics().successful_calls, 1);
+    }
+
+    #[test]
+    fn call_timing_out_records_a_failure() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        let result = breaker.call_timeout(Duration::from_millis(1), || {
+            std::thread::sleep(Duration::from_millis(50));
+            Ok::<_, ()>(7)
+        });
+        assert!(matches!(result, Err(Error::Timeout)));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn trip_reason_names_the_threshold_which_fired() {
+        let clock = TestClock::new();
+        let windowed = || {
+            StateMachine::builder()
+                .window_size(4)
+                .slow_call_threshold(Duration::from_secs(1))
+                .slow_call_rate_threshold(0.5)
+                .clock(clock.clone())
+        };
+
+        let consecutive = StateMachine::new(4, Duration::from_secs(60));
+        for _ in 0..4 {
+            let _ = consecutive.call(|| Err::<(), _>(()));
+        }
+        assert_eq!(
+            consecutive.trip_reason(),
+            Some(TripReason::ConsecutiveFailures)
+        );
+
+        let failing = windowed().build().unwrap();
+        let _ = failing.call(|| Err::<(), _>(()));
+        assert_eq!(failing.trip_reason(), Some(TripReason::FailureRate));
+
+        let slow = windowed().build().unwrap();
+        let _ = slow.call(|| {
+            clock.advance(Duration::from_secs(2));
+            Ok::<_, ()>(())
+        });
+        assert_eq!(slow.trip_reason(), Some(TripReason::SlowCallRate));
+
+        let fast = windowed().build().unwrap();
+        let _ = fast.call(|| Ok::<_, ()>(()));
+        assert_eq!(fast.trip_reason(), None);
+        fast.trip();
+        assert_eq!(fast.trip_reason(), Some(TripReason::Manual));
+    }
+
+    #[test]
+    fn consecutive_failures_counts_failures_below_the_threshold() {
+        let breaker = StateMachine::new(3, Duration::from_secs(60));
+        assert_eq!(breaker.consecutive_failures(), 0);
+        for _ in 0..2 {
+            let _ = breaker.call(|| Err::<(), _>(()));
+        }
+        assert_eq!(breaker.consecutive_failures(), 2);
+        assert_eq!(breaker.current_state(), State::Closed);
+    }
+
+    #[test]
+    fn failure_rate_waits_for_the_minimum_throughput() {
+        let breaker = StateMachine::builder()
+            .window_size(20)
+            .failure_rate_threshold(0.5)
+            .minimum_throughput(10)
+            .build()
+            .unwrap();
+        for _ in 0..2 {
+            let _ = breaker.call(|| Err::<(), _>(()));
+        }
+        assert_eq!(breaker.current_state(), State::Closed);
+
+        for _ in 0..5 {
+            let _ = breaker.call(|| Ok::<_, ()>(()));
+        }
+        for _ in 0..2 {
+            let _ = breaker.call(|| Err::<(), _>(()));
+        }
+        assert_eq!(breaker.current_state(), State::Closed);
+
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert_eq!(breaker.trip_reason(), Some(TripReason::FailureRate));
+    }
+
+    #[test]
+    fn failed_probe_advances_the_backoff() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(1))
+            .backoff_multiplier(2.0)
+            .backoff_on_half_open_failure(BackoffOnHalfOpenFailure::Escalate)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert_eq!(breaker.retry_after(), Some(Duration::from_secs(1)));
+
+        clock.advance(Duration::from_secs(2));
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert_eq!(breaker.trip_reason(), Some(TripReason::HalfOpenFailure));
+        assert_eq!(breaker.retry_after(), Some(Duration::from_secs(2)));
+    }
+
+    // Fail `probes` half-open probes in a row and return how long the circuit
+    // breaker reopened for after each of them.
+    fn reopen_timeouts(mode: Option<BackoffOnHalfOpenFailure>, probes: usize) -> Vec<Duration> {
+        let clock = TestClock::new();
+        let mut builder = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(1))
+            .backoff_multiplier(2.0)
+            .clock(clock.clone());
+        if let Some(mode) = mode {
+            builder = builder.backoff_on_half_open_failure(mode);
+        }
+        let breaker = builder.build().unwrap();
+        let _ = breaker.call(|| Err::<(), _>(()));
+
+        (0..probes)
+            .map(|_| {
+                clock.advance(breaker.retry_after().unwrap() + Duration::from_millis(1));
+                let _ = breaker.call(|| Err::<(), _>(()));
+                breaker.retry_after().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn failed_probes_repeat_the_last_timeout_by_default() {
+        assert_eq!(reopen_timeouts(None, 2), [1, 1].map(Duration::from_secs));
+        assert_eq!(
+            reopen_timeouts(Some(BackoffOnHalfOpenFailure::Repeat), 2),
+            [1, 1].map(Duration::from_secs)
+        );
+    }
+
+    #[test]
+    fn failed_probes_escalate_the_backoff() {
+        assert_eq!(
+            reopen_timeouts(Some(BackoffOnHalfOpenFailure::Escalate), 2),
+            [2, 4].map(Duration::from_secs)
+        );
+    }
+
+    #[test]
+    fn tripping_keeps_an_isolated_breaker_isolated() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        breaker.isolate();
+        breaker.trip();
+        assert_eq!(breaker.current_state(), State::ForcedOpen);
+        clock.advance(Duration::from_secs(3600));
+        assert!(!breaker.is_call_permitted());
+    }
+
+    #[test]
+    fn time_in_current_state_restarts_on_transitions() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(breaker.time_in_current_state(), Duration::from_secs(5));
+
+        breaker.trip();
+        assert_eq!(breaker.time_in_current_state(), Duration::ZERO);
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(breaker.time_in_current_state(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn caught_panic_records_a_failure() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        let result = breaker.call_catch_unwind(|| -> Result<(), ()> { panic!("boom") });
+
+        assert!(matches!(result, Err(Error::Panic)));
+        assert_eq!(breaker.metrics().failed_calls, 1);
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn name_round_trips_through_the_builder() {
+        let breaker = StateMachine::builder()
+            .name("payments-api")
+            .build()
+            .unwrap();
+        assert_eq!(breaker.name(), Some("payments-api"));
+        assert_eq!(StateMachine::new(1, Duration::ZERO).name(), None);
+
+        let names = Arc::new(Mutex::new(Vec::new()));
+        let seen = names.clone();
+        breaker.set_on_state_change(move |change| seen.lock().push(change.name.clone()));
+        breaker.trip();
+        assert_eq!(*names.lock(), [Some(Arc::from("payments-api"))]);
+    }
+
+    #[test]
+    fn jitter_spreads_the_open_windows_of_identical_breakers() {
+        let open_window = |seed| {
+            let breaker = StateMachine::builder()
+                .trip_timeout(Duration::from_secs(10))
+                .jitter(0.5)
+                .clock(TestClock::new())
+                .rng(SeededRng::new(seed))
+                .build()
+                .unwrap();
+            breaker.trip();
+            breaker.retry_after().unwrap()
+        };
+        let first = open_window(1);
+        let second = open_window(2);
+
+        assert_ne!(first, second);
+        for window in [first, second] {
+            assert!(window >= Duration::from_secs(5) && window <= Duration::from_secs(15));
+        }
+        assert_eq!(open_window(1), first);
+    }
+
+    #[test]
+    fn jitter_is_drawn_from_the_rng_on_every_trip() {
+        // Return the scripted numbers in turn.
+        struct Scripted(Mutex<Vec<f64>>);
+
+        impl Rng for Scripted {
+            fn next_f64(&self) -> f64 {
+                self.0.lock().remove(0)
+            }
+        }
+
+        let breaker = StateMachine::builder()
+            .trip_timeout(Duration::from_secs(10))
+            .jitter(0.5)
+            .clock(TestClock::new())
+            .rng(Scripted(Mutex::new(vec![0.0, 0.5, 0.75])))
+            .build()
+            .unwrap();
+        let mut windows = Vec::new();
+        for _ in 0..3 {
+            breaker.trip();
+            windows.push(breaker.retry_after().unwrap());
+            breaker.reset();
+        }
+        assert_eq!(windows, [5000, 10000, 12500].map(Duration::from_millis));
+    }
+
+    #[test]
+    fn recent_outcomes_lists_the_window_oldest_first() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .window_size(3)
+            .minimum_throughput(10)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        for failure in [true, false, true, false] {
+            clock.advance(Duration::from_secs(1));
+            let _ = breaker.call(|| if failure { Err(()) } else { Ok(()) });
+        }
+
+        let outcome = |secs, failure| Outcome {
+            at: Duration::from_secs(secs),
+            failure,
+            slow: false,
+        };
+        assert_eq!(
+            breaker.recent_outcomes(),
+            [outcome(2, false), outcome(3, true), outcome(4, false)]
+        );
+        assert!(breaker.recent_buckets().is_empty());
+    }
+
+    #[test]
+    fn time_window_lists_its_buckets_instead_of_outcomes() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .window_duration(Duration::from_secs(10))
+            .minimum_throughput(10)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let _ = breaker.call(|| Err::<(), _>(()));
+        clock.advance(Duration::from_millis(3500));
+        let _ = breaker.call(|| Ok::<_, ()>(()));
+        let _ = breaker.call(|| Err::<(), _>(()));
+
+        assert!(breaker.recent_outcomes().is_empty());
+        let buckets = breaker.recent_buckets();
+        let starts: Vec<_> = buckets.iter().map(|&(start, _)| start).collect();
+        assert_eq!(starts, [Duration::ZERO, Duration::from_secs(3)]);
+        let calls: Vec<_> = buckets
+            .iter()
+            .map(|(_, stats)| (stats.calls, stats.failures))
+            .collect();
+        assert_eq!(calls, [(1, 1), (2, 1)]);
+
+        // The first bucket expires once the window slides past it.
+        clock.advance(Duration::from_secs(7));
+        assert_eq!(breaker.recent_buckets().len(), 1);
+    }
+
+    #[test]
+    fn lowering_max_failures_trips_on_the_next_failure() {
+        let breaker = StateMachine::new(10, Duration::from_secs(60));
+        for _ in 0..5 {
+            let _ = breaker.call(|| Err::<(), _>(()));
+        }
+        breaker
+            .reconfigure(Config {
+                max_failures: 3,
+                trip_timeout: Duration::from_secs(60),
+                ..Config::default()
+            })
+            .unwrap();
+        assert_eq!(breaker.current_state(), State::Closed);
+        assert_eq!(breaker.consecutive_failures(), 3);
+
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn waiting_thread_is_woken_by_a_reset() {
+        let breaker = StateMachine::new(1, Duration::from_secs(3600));
+        breaker.trip();
+        let waiter = {
+            let breaker = breaker.clone();
+            thread::spawn(move || breaker.wait_until_permitted(Duration::from_secs(30)))
+        };
+        thread::sleep(Duration::from_millis(20));
+        let woken_at = Instant::now();
+        breaker.reset();
+
+        assert!(waiter.join().unwrap());
+        assert!(woken_at.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn waiting_gives_up_after_max_wait() {
+        let breaker = StateMachine::new(1, Duration::from_secs(3600));
+        breaker.trip();
+        assert!(!breaker.wait_until_permitted(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn imported_snapshot_stays_open_for_the_remaining_time() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .trip_timeout(Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        breaker.trip();
+        clock.advance(Duration::from_secs(4));
+        let snapshot = breaker.export_state();
+
+        let clock = TestClock::new();
+        let restored = StateMachine::builder()
+            .trip_timeout(Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        restored.import_state(snapshot);
+        assert_eq!(restored.export_state(), snapshot);
+        assert_eq!(restored.retry_after(), Some(Duration::from_secs(6)));
+        assert!(!restored.is_call_permitted());
+
+        clock.advance(Duration::from_secs(6) + Duration::from_millis(1));
+        assert!(restored.is_call_permitted());
+    }
+
+    #[test]
+    fn call_detailed_maps_each_state_to_its_outcome() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(10))
+            .half_open_max_calls(1)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        assert!(matches!(
+            breaker.call_detailed(|| Ok::<_, ()>(1)),
+            CallOutcome::Success(1)
+        ));
+        assert!(matches!(
+            breaker.call_detailed(|| Err::<(), _>("500")),
+            CallOutcome::Failure("500")
+        ));
+        assert!(matches!(
+            breaker.call_detailed(|| Ok::<_, ()>(1)),
+            CallOutcome::RejectedOpen { retry_after } if retry_after == Duration::from_secs(10)
+        ));
+
+        clock.advance(Duration::from_secs(11));
+        let prober = breaker.clone();
+        let busy = breaker.call_detailed(|| Ok::<_, ()>(prober.call_detailed(|| Ok::<_, ()>(1))));
+        assert!(matches!(
+            busy,
+            CallOutcome::Success(CallOutcome::RejectedHalfOpenBusy)
+        ));
+
+        breaker.isolate();
+        assert!(matches!(
+            breaker.call_detailed(|| Ok::<_, ()>(1)),
+            CallOutcome::RejectedOpen {
+                retry_after: Duration::MAX
+            }
+        ));
+    }
+
+    #[test]
+    fn call_detailed_tells_a_ramp_rejection_apart() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .trip_timeout(Duration::from_secs(1))
+            .recovery_ramp(Duration::from_secs(10), 0.0)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        breaker.trip();
+        clock.advance(Duration::from_secs(1));
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+        assert!(breaker.is_closed());
+
+        // The ramp admits no call at all right after closing.
+        assert!(matches!(
+            breaker.call_detailed(|| Ok::<_, ()>(1)),
+            CallOutcome::RejectedThrottled
+        ));
+    }
+
+    #[test]
+    fn half_open_probes_are_counted() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(1))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        for probe_fails in [true, false, true, true, false] {
+            breaker.trip();
+            clock.advance(Duration::from_secs(2));
+            let _ = breaker.call(|| if probe_fails { Err(()) } else { Ok(()) });
+        }
+
+        let metrics = breaker.metrics();
+        assert_eq!(metrics.half_open_successes, 2);
+        assert_eq!(metrics.half_open_failures, 3);
+    }
+
+    #[test]
+    fn panic_under_the_lock_does_not_brick_the_breaker() {
+        let panicked = Arc::new(AtomicBool::new(false));
+        let panic_once = panicked.clone();
+        let breaker = StateMachine::builder()
+            .policy(move |stats: &WindowStats| {
+                if !panic_once.swap(true, Ordering::Relaxed) {
+                    panic!("policy panicked");
+                }
+                stats.consecutive_failures >= 2
+            })
+            .build()
+            .unwrap();
+        let handle = breaker.clone();
+        let result = thread::spawn(move || handle.call(|| Err::<(), _>(()))).join();
+        assert!(result.is_err());
+        assert!(panicked.load(Ordering::Relaxed));
+
+        assert_eq!(breaker.current_state(), State::Closed);
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+        breaker.reset();
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+    }
+
+    #[test]
+    fn half_open_callback_fires_on_entering_half_open_only() {
+        let breaker = StateMachine::new(1, Duration::ZERO);
+        let entered = Arc::new(AtomicUsize::new(0));
+        let counted = entered.clone();
+        breaker.set_on_half_open(move || {
+            counted.fetch_add(1, Ordering::Relaxed);
+        });
+        breaker.trip();
+        breaker.isolate();
+        breaker.reset();
+        assert_eq!(entered.load(Ordering::Relaxed), 0);
+
+        breaker.trip();
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+        assert_eq!(breaker.current_state(), State::Closed);
+        assert_eq!(entered.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn sampler_sees_genuine_successes_only() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .slow_call_threshold(Duration::from_secs(1))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let sampled = Arc::new(Mutex::new(Vec::new()));
+        let seen = sampled.clone();
+        breaker.set_success_sampler(move |value: &u32| seen.lock().push(*value));
+
+        assert!(breaker.call(|| Ok::<u32, ()>(1)).is_ok());
+        assert!(breaker.call(|| Err::<u32, _>(())).is_err());
+        let slow = breaker.call(|| {
+            clock.advance(Duration::from_secs(2));
+            Ok::<u32, ()>(3)
+        });
+        assert!(matches!(slow, Ok(3)));
+        assert!(breaker.call(|| Ok::<u32, ()>(4)).is_ok());
+
+        assert_eq!(*sampled.lock(), [1, 4]);
+    }
+
+    #[test]
+    fn sampler_ignores_calls_returning_another_type() {
+        let breaker = StateMachine::default();
+        let sampled = Arc::new(Mutex::new(Vec::new()));
+        let seen = sampled.clone();
+        breaker.set_success_sampler(move |value: &String| seen.lock().push(value.clone()));
+
+        assert!(breaker.call(|| Ok::<_, ()>("borrowed")).is_ok());
+        assert!(breaker.call(|| Ok::<_, ()>(1)).is_ok());
+        assert!(sampled.lock().is_empty());
+
+        assert!(breaker.call(|| Ok::<_, ()>("owned".to_string())).is_ok());
+        assert_eq!(*sampled.lock(), ["owned"]);
+    }
+
+    #[test]
+    fn last_error_keeps_the_message_until_closed() {
+        let breaker = StateMachine::new(2, Duration::ZERO);
+        breaker.set_error_formatter(|err: &&str| err.to_string());
+        assert_eq!(breaker.last_error(), None);
+        let _ = breaker.call(|| Err::<(), _>("connection refused"));
+        let _ = breaker.call(|| Err::<(), _>("connection reset"));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+        assert_eq!(breaker.last_error().as_deref(), Some("connection reset"));
+
+        assert!(breaker.call(|| Ok::<_, &str>(())).is_ok());
+        assert_eq!(breaker.current_state(), State::Closed);
+        assert_eq!(breaker.last_error(), None);
+
+        let _ = breaker.call(|| Err::<(), _>("timed out"));
+        breaker.reset();
+        assert_eq!(breaker.last_error(), None);
+    }
+
+    #[test]
+    fn last_error_describes_every_kind_of_failure() {
+        let breaker = StateMachine::new(100, Duration::from_secs(60));
+        let _ = breaker.call(|| Err::<(), _>(404_u16));
+        assert_eq!(breaker.last_error().as_deref(), Some("u16"));
+
+        breaker.set_error_formatter(|status: &u16| format!("status {status}"));
+        breaker.acquire().unwrap().record_error(&503_u16);
+        assert_eq!(breaker.last_error().as_deref(), Some("status 503"));
+
+        let _ = breaker.call_timeout(Duration::from_millis(10), || {
+            thread::sleep(Duration::from_millis(200));
+            Ok::<_, u16>(())
+        });
+        assert_eq!(breaker.last_error().as_deref(), Some("call timed out"));
+
+        let _ = breaker.call_catch_unwind(|| -> Result<(), u16> { panic!("boom") });
+        assert_eq!(breaker.last_error().as_deref(), Some("call panicked"));
+    }
+
+    #[test]
+    fn open_breaker_returns_the_last_good_value() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        assert!(matches!(
+            breaker.call_or_last_good(|| Ok::<u32, ()>(7)),
+            Ok(7)
+        ));
+        let _ = breaker.call_or_last_good(|| Err::<u32, _>(()));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+
+        assert!(matches!(
+            breaker.call_or_last_good(|| Ok::<u32, ()>(8)),
+            Ok(7)
+        ));
+    }
+
+    #[test]
+    fn open_breaker_without_a_good_value_rejects() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        assert!(matches!(
+            breaker.call_or_last_good(|| Ok::<u32, ()>(7)),
+            Ok(7)
+        ));
+        breaker.reset();
+        breaker.trip();
+
+        assert!(matches!(
+            breaker.call_or_last_good(|| Ok::<u32, ()>(8)),
+            Err(Error::Rejected { .. })
+        ));
+    }
+
+    #[test]
+    fn stale_call_does_not_release_a_probe_slot() {
+        let breaker = StateMachine::builder()
+            .trip_timeout(Duration::ZERO)
+            .half_open_max_calls(1)
+            .build()
+            .unwrap();
+        let prober = breaker.clone();
+        let result = breaker.call(|| {
+            // Trip and go half-open while the call runs, letting a probe take
+            // the only slot.
+            prober.trip();
+            assert!(prober.is_call_permitted());
+            Ok::<_, ()>(())
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(breaker.current_state(), State::HalfOpen);
+        assert!(!breaker.is_call_permitted());
+        assert_eq!(breaker.metrics().half_open_successes, 0);
+    }
+
+    #[test]
+    fn a_fixed_seed_repeats_the_randomized_behavior() {
+        let run = || {
+            let clock = TestClock::new();
+            let breaker = StateMachine::builder()
+                .trip_timeout(Duration::from_secs(10))
+                .jitter(0.5)
+                .recovery_ramp(Duration::from_secs(10), 0.5)
+                .clock(clock.clone())
+                .rng(SeededRng::new(42))
+                .build()
+                .unwrap();
+            breaker.trip();
+            let window = breaker.retry_after().unwrap();
+            clock.advance(window);
+            assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+            let admissions: Vec<_> = (0..100).map(|_| breaker.is_call_permitted()).collect();
+            (window, admissions)
+        };
+        let (window, admissions) = run();
+        assert!(admissions.contains(&true) && admissions.contains(&false));
+        assert_eq!(run(), (window, admissions));
+    }
+
+    #[test]
+    fn would_probe_peeks_without_changing_the_state() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(10))
+            .half_open_max_calls(2)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        assert!(!breaker.would_probe());
+
+        breaker.trip();
+        assert!(!breaker.would_probe());
+        clock.advance(Duration::from_secs(10));
+        assert!(breaker.would_probe());
+        assert!(breaker.would_probe());
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+
+        assert!(breaker.is_call_permitted());
+        assert_eq!(breaker.current_state(), State::HalfOpen);
+        assert!(breaker.would_probe());
+        assert!(breaker.is_call_permitted());
+        assert!(!breaker.would_probe());
+    }
+
+    #[test]
+    fn warmup_counts_failures_without_tripping() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(3)
+            .warmup(Duration::from_secs(5))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        for _ in 0..5 {
+            let _ = breaker.call(|| Err::<(), _>(()));
+        }
+        assert_eq!(breaker.current_state(), State::Closed);
+        assert_eq!(breaker.consecutive_failures(), 5);
+
+        clock.advance(Duration::from_secs(5));
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn transition_history_keeps_the_latest_transitions_in_order() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(10))
+            .history_capacity(2)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let _ = breaker.call(|| Err::<(), _>(()));
+        clock.advance(Duration::from_secs(10));
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+
+        let history = breaker.transition_history();
+        assert_eq!(history.len(), 2);
+        assert!(matches!(history[0].from, State::Open { .. }));
+        assert_eq!(
+            (history[0].to, history[0].at),
+            (State::HalfOpen, Duration::from_secs(10))
+        );
+        assert_eq!(
+            (history[1].from, history[1].to, history[1].at),
+            (State::HalfOpen, State::Closed, Duration::from_secs(10))
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[::tokio::test(start_paused = true)]
+    async fn async_timeout_records_a_failure_on_elapse() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        let result = breaker
+            .call_async_timeout(Duration::from_secs(1), || async {
+                ::tokio::time::sleep(Duration::from_millis(10)).await;
+                Ok::<_, ()>(7)
+            })
+            .await;
+        assert!(matches!(result, Ok(7)));
+        assert_eq!(breaker.current_state(), State::Closed);
+
+        let result = breaker
+            .call_async_timeout(Duration::from_secs(1), || async {
+                ::tokio::time::sleep(Duration::from_secs(2)).await;
+                Ok::<_, ()>(7)
+            })
+            .await;
+        assert!(matches!(result, Err(Error::Timeout)));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn infallible_call_is_rejected_when_open() {
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        assert!(matches!(breaker.call_infallible(|| 7), Ok(7)));
+        assert_eq!(breaker.current_state(), State::Closed);
+
+        breaker.trip();
+        let mut called = false;
+        let result = breaker.call_infallible(|| called = true);
+        assert!(matches!(result, Err(Error::Rejected { .. })));
+        assert!(!called);
+    }
+
+    #[test]
+    fn trip_only_on_ignores_the_errors_it_rejects() {
+        let breaker = StateMachine::builder()
+            .max_failures(2)
+            .trip_only_on(|status: &&str| status.starts_with('5'))
+            .build()
+            .unwrap();
+        for _ in 0..5 {
+            let result = breaker.call(|| Err::<(), _>("404"));
+            assert!(matches!(result, Err(Error::Inner("404"))));
+        }
+        assert_eq!(breaker.current_state(), State::Closed);
+
+        for _ in 0..2 {
+            let _ = breaker.call(|| Err::<(), _>("500"));
+        }
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn trip_only_on_composes_with_the_call_classifier() {
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_only_on(|status: &&str| status.starts_with('5'))
+            .build()
+            .unwrap();
+        let _ = breaker.call_with(|_| true, || Err::<(), _>("404"));
+        let _ = breaker.call_with(|status| *status != "503", || Err::<(), _>("503"));
+        breaker.acquire().unwrap().record_error(&"404");
+        assert_eq!(breaker.current_state(), State::Closed);
+        assert_eq!(breaker.metrics().failed_calls, 0);
+
+        breaker.acquire().unwrap().record_error(&"500");
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn count_timeouts_only_trips_on_timeouts_only() {
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .count_timeouts_only::<&str>()
+            .build()
+            .unwrap();
+        let result = breaker.call_timeout(Duration::from_secs(5), || Err::<(), _>("500"));
+        assert!(matches!(result, Err(Error::Inner("500"))));
+        assert_eq!(breaker.current_state(), State::Closed);
+
+        let result = breaker.call_timeout(Duration::from_millis(10), || {
+            thread::sleep(Duration::from_millis(200));
+            Ok::<_, &str>(())
+        });
+        assert!(matches!(result, Err(Error::Timeout)));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+
+    #[test]
+    fn dwell_times_add_up_the_time_in_each_state() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        clock.advance(Duration::from_secs(3));
+        breaker.trip();
+        clock.advance(Duration::from_secs(10));
+        let result = breaker.call(|| {
+            clock.advance(Duration::from_secs(2));
+            Ok::<_, ()>(())
+        });
+        assert!(result.is_ok());
+        assert_eq!(breaker.current_state(), State::Closed);
+        clock.advance(Duration::from_secs(4));
+
+        assert_eq!(
+            breaker.dwell_times(),
+            DwellTimes {
+                closed: Duration::from_secs(7),
+                open: Duration::from_secs(10),
+                half_open: Duration::from_secs(2),
+            }
+        );
+    }
+
+    #[test]
+    fn per_item_batch_failures_trip_at_once() {
+        let breaker = StateMachine::builder()
+            .max_failures(10)
+            .batch_failure(BatchFailure::PerItem)
+            .build()
+            .unwrap();
+        let started = Instant::now();
+        let result = breaker.call_batch(u32::MAX, || Err::<(), _>(()));
+        assert!(matches!(result, Err(Error::Inner(()))));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+        assert_eq!(breaker.metrics().failed_calls, 1);
+    }
+
+    #[test]
+    fn a_failed_batch_counts_once_by_default() {
+        let breaker = StateMachine::new(2, Duration::from_secs(60));
+        assert!(breaker.call_batch(100, || Err::<(), _>(())).is_err());
+        assert_eq!(breaker.current_state(), State::Closed);
+        assert!(breaker.call_batch(100, || Err::<(), _>(())).is_err());
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+        assert!(matches!(
+            breaker.call_batch(1, || Ok::<_, ()>(())),
+            Err(Error::Rejected { .. })
+        ));
+    }
+
+    #[test]
+    fn warning_fires_once_per_crossing() {
+        let breaker = StateMachine::builder()
+            .max_failures(5)
+            .warn_threshold(2)
+            .build()
+            .unwrap();
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let warned = warnings.clone();
+        breaker.set_on_warn(move |failures| warned.lock().push(failures));
+
+        for _ in 0..4 {
+            let _ = breaker.call(|| Err::<(), _>(()));
+        }
+        assert_eq!(breaker.current_state(), State::Closed);
+        assert_eq!(*warnings.lock(), [2]);
+
+        let _ = breaker.call(|| Ok::<_, ()>(()));
+        for _ in 0..2 {
+            let _ = breaker.call(|| Err::<(), _>(()));
+        }
+        assert_eq!(*warnings.lock(), [2, 2]);
+    }
+
+    #[test]
+    fn dropping_breakers_stops_their_health_checks() {
+        let checks = Arc::new(AtomicUsize::new(0));
+        let breakers: Vec<_> = (0..50)
+            .map(|_| {
+                let breaker = StateMachine::new(1, Duration::from_secs(60));
+                let checks = checks.clone();
+                breaker.set_health_check(Duration::from_millis(1), move || {
+                    checks.fetch_add(1, Ordering::Relaxed);
+                    false
+                });
+                breaker.trip();
+                breaker
+            })
+            .collect();
+        while checks.load(Ordering::Relaxed) < 50 {
+            thread::yield_now();
+        }
+
+        drop(breakers);
+        // A thread which held the last handle drops it without joining itself.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Arc::strong_count(&checks) > 1 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(Arc::strong_count(&checks), 1);
+        let stopped_at = checks.load(Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(checks.load(Ordering::Relaxed), stopped_at);
+    }
+
+    #[test]
+    fn shutdown_joins_the_health_check() {
+        let checks = Arc::new(AtomicUsize::new(0));
+        let breaker = StateMachine::new(1, Duration::from_secs(60));
+        let counted = checks.clone();
+        breaker.set_health_check(Duration::from_millis(1), move || {
+            counted.fetch_add(1, Ordering::Relaxed);
+            false
+        });
+        breaker.trip();
+        while checks.load(Ordering::Relaxed) == 0 {
+            thread::yield_now();
+        }
+
+        breaker.shutdown();
+        let stopped_at = checks.load(Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(checks.load(Ordering::Relaxed), stopped_at);
+        assert!(breaker.inner.health_check_thread.lock().is_none());
+    }
+
+    #[test]
+    fn config_reflects_a_reconfiguration() {
+        let breaker = StateMachine::new(3, Duration::from_secs(60));
+        assert_eq!(breaker.config().max_failures, 3);
+
+        let config = Config {
+            max_failures: 10,
+            trip_timeout: Duration::from_secs(5),
+            window: Some(SlidingWindow::Count(20)),
+            failure_rate_threshold: 0.25,
+            ..breaker.config()
+        };
+        breaker.reconfigure(config.clone()).unwrap();
+        assert_eq!(breaker.config(), config);
+    }
+
+    #[test]
+    fn reconfigure_keeps_the_config_when_the_new_one_is_invalid() {
+        let breaker = StateMachine::new(3, Duration::from_secs(60));
+        let config = Config {
+            max_failures: 0,
+            ..breaker.config()
+        };
+        assert_eq!(
+            breaker.reconfigure(config),
+            Err(ConfigError::ZeroMaxFailures)
+        );
+        assert_eq!(breaker.config().max_failures, 3);
+    }
+
+    #[test]
+    fn cost_budget_trips_on_expensive_failures_only() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .cost_budget(10, Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let weigh = |err: &&str| if *err == "timeout" { 4 } else { 1 };
+        for _ in 0..30 {
+            let _ = breaker.call_weighted(weigh, || Err::<(), _>("refused"));
+            clock.advance(Duration::from_secs(1));
+        }
+        assert_eq!(breaker.current_state(), State::Closed);
+
+        for _ in 0..2 {
+            let _ = breaker.call_weighted(weigh, || Err::<(), _>("timeout"));
+        }
+        assert_eq!(breaker.current_state(), State::Closed);
+        let _ = breaker.call_weighted(weigh, || Err::<(), _>("timeout"));
+        assert!(matches!(
+            breaker.current_state(),
+            State::Open {
+                reason: TripReason::CostBudget,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn state_predicates_match_each_state() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let predicates = |breaker: &StateMachine| {
+            (
+                breaker.is_closed(),
+                breaker.is_open(),
+                breaker.is_half_open(),
+            )
+        };
+        assert_eq!(predicates(&breaker), (true, false, false));
+
+        breaker.trip();
+        assert_eq!(predicates(&breaker), (false, true, false));
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(predicates(&breaker), (false, true, false));
+
+        assert!(breaker.is_call_permitted());
+        assert_eq!(predicates(&breaker), (false, false, true));
+
+        breaker.isolate();
+        assert_eq!(predicates(&breaker), (false, true, false));
+    }
+
+    #[test]
+    fn records_outcomes_out_of_band() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(3)
+            .trip_timeout(Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        for _ in 0..3 {
+            assert!(breaker.is_call_permitted());
+            breaker.record_failure(Duration::from_millis(5));
+        }
+        assert!(!breaker.is_call_permitted());
+
+        clock.advance(Duration::from_secs(10));
+        assert!(breaker.is_call_permitted());
+        breaker.record_success(Duration::from_millis(5));
+        assert!(breaker.is_closed());
+        assert_eq!(breaker.metrics().failed_calls, 3);
+    }
+
+    #[test]
+    fn timeout_fn_is_keyed_on_the_reopen_count() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .backoff_on_half_open_failure(BackoffOnHalfOpenFailure::Escalate)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        breaker.set_timeout_fn(|reopens| Duration::from_secs(10 * (u64::from(reopens) + 1)));
+
+        breaker.trip();
+        for timeout in [10, 20, 30] {
+            assert_eq!(breaker.retry_after(), Some(Duration::from_secs(timeout)));
+            clock.advance(Duration::from_secs(timeout));
+            assert!(breaker.is_call_permitted());
+            breaker.record_failure(Duration::ZERO);
+        }
+    }
+
+    #[cfg(feature = "hdrhistogram")]
+    #[test]
+    fn latency_percentiles_of_known_durations() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        assert_eq!(breaker.latency_percentile(50.0), None);
+
+        for millis in 1..=100 {
+            let _ = breaker.call(|| {
+                clock.advance(Duration::from_millis(millis));
+                Ok::<_, ()>(())
+            });
+        }
+        // Latencies are tracked to 3 significant figures.
+        let percentile = |p| breaker.latency_percentile(p).unwrap().as_millis();
+        assert_eq!(percentile(50.0), 50);
+        assert_eq!(percentile(95.0), 95);
+        assert_eq!(percentile(99.0), 99);
+    }
+
+    // A circuit breaker which is half-open with a single probe slot.
+    fn half_open() -> StateMachine {
+        let breaker = StateMachine::new(1, Duration::ZERO);
+        breaker.trip();
+        breaker
+    }
+
+    #[test]
+    fn half_open_permits_a_single_probe_by_default() {
+        let breaker = half_open();
+        let prober = breaker.clone();
+        let result = breaker.call(|| {
+            assert!(prober.is_half_open());
+            Ok::<_, ()>(prober.call(|| Ok::<_, ()>(())))
+        });
+
+        assert!(matches!(result, Ok(Err(Error::Rejected { .. }))));
+        assert!(breaker.is_closed());
+    }
+
+    #[test]
+    fn panicking_probe_records_a_failure() {
+        let breaker = half_open();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            breaker.call(|| -> Result<(), ()> { panic!("probe panicked") })
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(breaker.metrics().half_open_failures, 1);
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+        assert!(breaker.is_closed());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn dropped_async_probe_records_a_failure() {
+        use std::future::{self, Future};
+        use std::task::{Context, Poll};
+
+        let breaker = half_open();
+        let mut call = Box::pin(breaker.call_async(future::pending::<Result<(), ()>>));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(call.as_mut().poll(&mut cx).is_pending());
+        drop(call);
+
+        assert_eq!(breaker.metrics().half_open_failures, 1);
+        let mut call = Box::pin(breaker.call_async(|| future::ready(Ok::<_, ()>(()))));
+        assert!(matches!(call.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+    }
+
+    #[test]
+    fn bare_permission_gives_back_its_probe_slot_after_the_probe_timeout() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(10))
+            .probe_timeout(Duration::from_secs(5))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        breaker.trip();
+        clock.advance(Duration::from_secs(10));
+        assert!(breaker.is_call_permitted());
+        assert!(!breaker.is_call_permitted());
+
+        clock.advance(Duration::from_secs(5));
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+        assert!(breaker.is_closed());
+    }
+
+    #[test]
+    fn breaker_with_callbacks_is_shared_from_a_static() {
+        static BREAKER: OnceLock<StateMachine> = OnceLock::new();
+        let breaker = BREAKER.get_or_init(|| StateMachine::new(1, Duration::from_secs(60)));
+        let changes = Arc::new(AtomicUsize::new(0));
+        let counted = changes.clone();
+        breaker.set_on_state_change(move |_| {
+            counted.fetch_add(1, Ordering::Relaxed);
+        });
+
+        thread::spawn(|| BREAKER.get().unwrap().trip())
+            .join()
+            .unwrap();
+        assert!(breaker.is_open());
+        assert_eq!(changes.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn traces_a_failure_which_trips() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let (result, trace) = breaker.call_traced(|| Err::<(), _>("refused"));
+        assert!(matches!(result, Err(Error::Inner("refused"))));
+        assert_eq!(
+            trace,
+            TraceInfo {
+                before: State::Closed,
+                probe: false,
+                after: State::Open {
+                    remaining: Duration::from_secs(10),
+                    reason: TripReason::ConsecutiveFailures,
+                },
+            }
+        );
+
+        clock.advance(Duration::from_secs(10));
+        let (result, trace) = breaker.call_traced(|| Ok::<_, ()>(()));
+        assert!(result.is_ok());
+        assert!(trace.probe);
+        assert_eq!(trace.after, State::Closed);
+    }
+
+    #[test]
+    fn tick_goes_half_open_once_the_timeout_expired() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let half_opened = Arc::new(AtomicUsize::new(0));
+        let counted = half_opened.clone();
+        breaker.set_on_half_open(move || {
+            counted.fetch_add(1, Ordering::Relaxed);
+        });
+        breaker.trip();
+
+        clock.advance(Duration::from_secs(9));
+        assert!(!breaker.tick());
+        assert!(breaker.is_open());
+        clock.advance(Duration::from_secs(1));
+        assert!(breaker.tick());
+        assert!(breaker.is_half_open());
+        assert_eq!(half_opened.load(Ordering::Relaxed), 1);
+        assert!(breaker.is_call_permitted());
+    }
+
+    #[test]
+    fn starts_open_and_rejects_the_first_call() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .initial_state(State::Open {
+                remaining: Duration::from_secs(10),
+                reason: TripReason::Manual,
+            })
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let mut called = false;
+        let result = breaker.call(|| {
+            called = true;
+            Ok::<_, ()>(())
+        });
+        assert!(matches!(
+            result,
+            Err(Error::Rejected {
+                state: StateLabel::Open,
+                retry_after: Some(remaining),
+            }) if remaining == Duration::from_secs(10)
+        ));
+        assert!(!called);
+
+        clock.advance(Duration::from_secs(10));
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+        assert!(breaker.is_closed());
+    }
+
+    #[test]
+    fn settling_tolerates_more_failures_after_closing() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(2)
+            .trip_timeout(Duration::ZERO)
+            .settling(Duration::from_secs(10), 2)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        breaker.trip();
+        assert!(breaker.call(|| Ok::<_, ()>(())).is_ok());
+        assert!(breaker.is_closed());
+
+        for _ in 0..3 {
+            breaker.record_failure(Duration::ZERO);
+        }
+        assert!(breaker.is_closed());
+        breaker.record_success(Duration::ZERO);
+
+        clock.advance(Duration::from_secs(10));
+        for _ in 0..2 {
+            breaker.record_failure(Duration::ZERO);
+        }
+        assert!(breaker.is_open());
+    }
+
+    #[test]
+    fn subscribers_receive_every_event_in_order() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let subscribers = [breaker.subscribe(), breaker.subscribe()];
+
+        let _ = breaker.call(|| Ok::<_, ()>(()));
+        let _ = breaker.call(|| Err::<(), _>(()));
+        let _ = breaker.call(|| Ok::<_, ()>(()));
+
+        let trip = StateChange {
+            name: None,
+            from: State::Closed,
+            to: State::Open {
+                remaining: Duration::from_secs(10),
+                reason: TripReason::ConsecutiveFailures,
+            },
+        };
+        for subscriber in subscribers {
+            let events: Vec<_> = subscriber.try_iter().collect();
+            assert_eq!(
+                events,
+                [
+                    BreakerEvent::Success {
+                        elapsed: Duration::ZERO
+                    },
+                    BreakerEvent::Failure {
+                        elapsed: Duration::ZERO
+                    },
+                    BreakerEvent::StateChange(trip.clone()),
+                    BreakerEvent::Rejected,
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn rejection_carries_a_shrinking_retry_after() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .trip_timeout(Duration::from_secs(10))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        breaker.trip();
+        let retry_after = || match breaker.call(|| Ok::<_, ()>(())) {
+            Err(Error::Rejected {
+                state: StateLabel::Open,
+                retry_after,
+            }) => retry_after,
+            _ => panic!("the call was not rejected as open"),
+        };
+        assert_eq!(retry_after(), Some(Duration::from_secs(10)));
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(retry_after(), Some(Duration::from_secs(6)));
+
+        breaker.isolate();
+        assert!(matches!(
+            breaker.call(|| Ok::<_, ()>(())),
+            Err(Error::Rejected {
+                state: StateLabel::ForcedOpen,
+                retry_after: None,
+            })
+        ));
+    }
+
+    #[test]
+    fn slow_probe_counts_as_failure_with_fail_slow_probes() {
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .max_failures(1)
+            .trip_timeout(Duration::ZERO)
+            .slow_call_threshold(Duration::from_secs(1))
+            .fail_slow_probes(true)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        breaker.trip();
+        let events = breaker.subscribe();
+        let result = breaker.call(|| {
+            clock.advance(Duration::from_secs(2));
+            Ok::<_, ()>(())
+        });
+
+        assert!(result.is_ok());
+        assert!(breaker.is_open());
+        let metrics = breaker.metrics();
+        assert_eq!(metrics.successful_calls, 0);
+        assert_eq!(metrics.failed_calls, 1);
+        assert_eq!(metrics.half_open_successes, 0);
+        assert_eq!(metrics.half_open_failures, 1);
+        let events: Vec<_> = events.try_iter().collect();
+        assert!(events.contains(&BreakerEvent::Failure {
+            elapsed: Duration::from_secs(2)
+        }));
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, BreakerEvent::Success { .. })));
+    }
+
+    #[test]
+    fn default_breaker_trips_after_five_failures() {
+        let breaker = StateMachine::default();
+        assert!(breaker.is_call_permitted());
+        breaker.record_success(Duration::ZERO);
+
+        for _ in 0..4 {
+            let _ = breaker.call(|| Err::<(), _>(()));
+        }
+        assert!(breaker.is_closed());
+        let _ = breaker.call(|| Err::<(), _>(()));
+        assert!(breaker.is_open());
+        assert_eq!(breaker.config().trip_timeout, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn from_config_rejects_an_invalid_config() {
+        let config = Config {
+            max_failures: 0,
+            ..Config::default()
+        };
+        let result = StateMachine::from_config(config);
+        assert_eq!(result.err(), Some(ConfigError::ZeroMaxFailures));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid circuit breaker config")]
+    fn new_panics_on_zero_max_failures() {
+        let _ = StateMachine::new(0, Duration::from_secs(60));
+    }
+}
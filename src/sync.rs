@@ -0,0 +1,109 @@
+//! The locks guarding shared state, from `std` or, with the `parking_lot`
+//! feature, from `parking_lot`, behind the same interface.
+//!
+//! A `std` lock poisoned by a panic while it was held, e.g. in a `Policy`, is
+//! taken anyway rather than panicking in turn, as `parking_lot` does. The
+//! guarded state is then whatever the panicking thread left: a `Machine` may
+//! have counted an outcome without acting on it, which the next outcome
+//! catches up with, but is never left without a state.
+
+use std::time::Duration;
+
+#[cfg(feature = "parking_lot")]
+pub(crate) use parking_lot::{MutexGuard, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(feature = "parking_lot"))]
+pub(crate) use std::sync::{MutexGuard, RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(feature = "parking_lot")]
+use parking_lot as imp;
+#[cfg(not(feature = "parking_lot"))]
+use std::sync as imp;
+#[cfg(not(feature = "parking_lot"))]
+use std::sync::PoisonError;
+
+#[derive(Debug, Default)]
+pub(crate) struct Mutex<T>(imp::Mutex<T>);
+
+impl<T> Mutex<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Mutex(imp::Mutex::new(value))
+    }
+
+    #[cfg(feature = "parking_lot")]
+    pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+        self.0.lock()
+    }
+
+    #[cfg(not(feature = "parking_lot"))]
+    pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RwLock<T>(imp::RwLock<T>);
+
+impl<T> RwLock<T> {
+    pub(crate) fn new(value: T) -> Self {
+        RwLock(imp::RwLock::new(value))
+    }
+
+    #[cfg(feature = "parking_lot")]
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.0.read()
+    }
+
+    #[cfg(not(feature = "parking_lot"))]
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[cfg(feature = "parking_lot")]
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.0.write()
+    }
+
+    #[cfg(not(feature = "parking_lot"))]
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Condvar(imp::Condvar);
+
+impl Condvar {
+    pub(crate) fn new() -> Self {
+        Condvar(imp::Condvar::new())
+    }
+
+    pub(crate) fn notify_all(&self) {
+        self.0.notify_all();
+    }
+
+    /// Release the lock of `guard` until notified or until `timeout` elapsed,
+    /// and take it again.
+    #[cfg(feature = "parking_lot")]
+    pub(crate) fn wait_timeout<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> MutexGuard<'a, T> {
+        self.0.wait_for(&mut guard, timeout);
+        guard
+    }
+
+    /// Release the lock of `guard` until notified or until `timeout` elapsed,
+    /// and take it again.
+    #[cfg(not(feature = "parking_lot"))]
+    pub(crate) fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> MutexGuard<'a, T> {
+        self.0
+            .wait_timeout(guard, timeout)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+    }
+}
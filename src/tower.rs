@@ -0,0 +1,233 @@
+//! A `tower` middleware calling the inner service within a circuit breaker.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::state_machine::{every_error, StateMachine};
+
+/// A `tower::Layer` wrapping services with a `CircuitBreaker` service.
+///
+/// All services created by the same layer share the circuit breaker.
+#[derive(Clone)]
+pub struct CircuitBreakerLayer {
+    breaker: StateMachine,
+}
+
+impl CircuitBreakerLayer {
+    pub fn new(breaker: StateMachine) -> Self {
+        CircuitBreakerLayer { breaker }
+    }
+}
+
+impl<S> ::tower::Layer<S> for CircuitBreakerLayer {
+    type Service = CircuitBreaker<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CircuitBreaker::new(inner, self.breaker.clone())
+    }
+}
+
+/// A `tower::Service` calling the inner service only when the circuit breaker
+/// permits it.
+///
+/// `poll_ready` asks the circuit breaker for permission first and fails with
+/// `Error::Rejected` when it is refused. Errors of the inner service are
+/// returned as `Error::Inner` and recorded as failure.
+pub struct CircuitBreaker<S> {
+    inner: S,
+    breaker: StateMachine,
+    // The generation of the permission `poll_ready` got which no call has
+    // used yet, if any.
+    permitted: Option<u64>,
+}
+
+impl<S> CircuitBreaker<S> {
+    pub fn new(inner: S, breaker: StateMachine) -> Self {
+        CircuitBreaker {
+            inner,
+            breaker,
+            permitted: None,
+        }
+    }
+
+    /// Return the circuit breaker of this service.
+    pub fn breaker(&self) -> &StateMachine {
+        &self.breaker
+    }
+}
+
+impl<S: Clone> Clone for CircuitBreaker<S> {
+    fn clone(&self) -> Self {
+        CircuitBreaker::new(self.inner.clone(), self.breaker.clone())
+    }
+}
+
+impl<S> Drop for CircuitBreaker<S> {
+    fn drop(&mut self) {
+        if let Some(generation) = self.permitted {
+            self.breaker.release_since(generation);
+        }
+    }
+}
+
+impl<S, Request> ::tower::Service<Request> for CircuitBreaker<S>
+where
+    S: ::tower::Service<Request>,
+    S::Response: 'static,
+    S::Error: 'static,
+{
+    type Response = S::Response;
+    type Error = Error<S::Error>;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let generation = match self.permitted {
+            Some(generation) => generation,
+            None => match self.breaker.acquire_generation() {
+                Some(generation) => *self.permitted.insert(generation),
+                None => return Poll::Ready(Err(self.breaker.rejected())),
+            },
+        };
+
+        match self.inner.poll_ready(cx) {
+            Poll::Ready(Err(err)) => {
+                self.permitted = None;
+                self.breaker.on_error_since(generation, Duration::ZERO, 1);
+                Poll::Ready(Err(Error::Inner(err)))
+            }
+            poll => poll.map_err(Error::Inner),
+        }
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let generation = self
+            .permitted
+            .take()
+            .expect("poll_ready must return Ready(Ok(())) before calling the service");
+        ResponseFuture {
+            future: Box::pin(self.inner.call(request)),
+            breaker: Some(self.breaker.clone()),
+            start: self.breaker.timestamp(),
+            generation,
+        }
+    }
+}
+
+/// The response future of the `CircuitBreaker` service.
+///
+/// A future dropped before it completes is recorded as failure.
+pub struct ResponseFuture<F> {
+    future: Pin<Box<F>>,
+    // Taken once the outcome is recorded.
+    breaker: Option<StateMachine>,
+    start: Duration,
+    // The breaker's generation when the call was permitted.
+    generation: u64,
+}
+
+impl<F, T, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+    T: 'static,
+    E: 'static,
+{
+    type Output = Result<T, Error<E>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = match self.future.as_mut().poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let result = result.map_err(Error::Inner);
+        if let Some(breaker) = self.breaker.take() {
+            let elapsed = breaker.elapsed(self.start);
+            breaker.record(self.generation, elapsed, &result, every_error);
+        }
+        Poll::Ready(result)
+    }
+}
+
+impl<F> Drop for ResponseFuture<F> {
+    fn drop(&mut self) {
+        if let Some(breaker) = self.breaker.take() {
+            breaker.on_error_since(self.generation, breaker.elapsed(self.start), 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{self, Ready};
+    use std::task::Waker;
+    use std::time::Duration;
+
+    use ::tower::{Layer, Service};
+
+    use super::*;
+    use crate::state::State;
+
+    // A service which fails its first `failures` requests.
+    struct Flaky {
+        failures: usize,
+        calls: usize,
+    }
+
+    impl Service<u32> for Flaky {
+        type Response = u32;
+        type Error = &'static str;
+        type Future = Ready<Result<u32, &'static str>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: u32) -> Self::Future {
+            self.calls += 1;
+            if self.calls <= self.failures {
+                future::ready(Err("unavailable"))
+            } else {
+                future::ready(Ok(request))
+            }
+        }
+    }
+
+    fn send<S: Service<u32>>(service: &mut S, request: u32) -> Result<S::Response, S::Error> {
+        let mut cx = Context::from_waker(Waker::noop());
+        let Poll::Ready(ready) = service.poll_ready(&mut cx) else {
+            panic!("the service is not ready");
+        };
+        ready?;
+        let mut response = Box::pin(service.call(request));
+        match response.as_mut().poll(&mut cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => panic!("the response is pending"),
+        }
+    }
+
+    #[test]
+    fn short_circuits_a_failing_service() {
+        let breaker = StateMachine::new(3, Duration::from_secs(60));
+        let layer = CircuitBreakerLayer::new(breaker.clone());
+        let mut service = layer.layer(Flaky {
+            failures: 3,
+            calls: 0,
+        });
+
+        for _ in 0..3 {
+            assert!(matches!(
+                send(&mut service, 1),
+                Err(Error::Inner("unavailable"))
+            ));
+        }
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+        assert!(matches!(send(&mut service, 1), Err(Error::Rejected { .. })));
+        assert_eq!(service.inner.calls, 3);
+
+        breaker.reset();
+        assert!(matches!(send(&mut service, 4), Ok(4)));
+    }
+}
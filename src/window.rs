@@ -0,0 +1,314 @@
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::config::SlidingWindow;
+
+/// The number of buckets a time-based sliding window is split into.
+const TIME_WINDOW_BUCKETS: u32 = 10;
+
+/// The outcome of a single call recorded in a sliding window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    /// When the outcome was recorded, as the time since the circuit breaker's
+    /// epoch.
+    pub at: Duration,
+    /// Whether the call failed.
+    pub failure: bool,
+    /// Whether the call took longer than the slow call threshold.
+    pub slow: bool,
+}
+
+/// The aggregated outcomes of the calls within a sliding window.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WindowStats {
+    /// The number of calls.
+    pub calls: usize,
+    /// The number of failed calls.
+    pub failures: usize,
+    /// The number of calls which took longer than the slow call threshold.
+    pub slow_calls: usize,
+    /// The number of consecutive failures in the closed state, which the
+    /// circuit breaker counts whether or not there is a sliding window.
+    pub consecutive_failures: usize,
+}
+
+impl WindowStats {
+    fn add(&mut self, outcome: Outcome, n: usize) {
+        self.calls += n;
+        self.failures += outcome.failure as usize * n;
+        self.slow_calls += outcome.slow as usize * n;
+    }
+
+    fn remove(&mut self, outcome: Outcome) {
+        self.calls -= 1;
+        self.failures -= outcome.failure as usize;
+        self.slow_calls -= outcome.slow as usize;
+    }
+
+    fn merge(mut self, other: WindowStats) -> Self {
+        self.calls += other.calls;
+        self.failures += other.failures;
+        self.slow_calls += other.slow_calls;
+        self
+    }
+
+    /// Return the fraction of failed calls, or 0.0 without any calls.
+    pub fn failure_rate(&self) -> f64 {
+        self.rate(self.failures)
+    }
+
+    /// Return the fraction of slow calls, or 0.0 without any calls.
+    pub fn slow_call_rate(&self) -> f64 {
+        self.rate(self.slow_calls)
+    }
+
+    fn rate(&self, count: usize) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            count as f64 / self.calls as f64
+        }
+    }
+}
+
+/// A sliding window of call outcomes.
+#[derive(Debug)]
+pub(crate) enum Window {
+    Count(CountWindow),
+    Time(TimeWindow),
+}
+
+impl Window {
+    pub(crate) fn new(kind: SlidingWindow, now: Duration) -> Self {
+        match kind {
+            SlidingWindow::Count(size) => Window::Count(CountWindow::new(size)),
+            SlidingWindow::Time(duration) => Window::Time(TimeWindow::new(duration, now)),
+        }
+    }
+
+    /// Record `n` calls with the same outcome.
+    pub(crate) fn record(&mut self, outcome: Outcome, n: usize, now: Duration) {
+        match self {
+            // Beyond the size of the window, the outcomes only evict each other.
+            Window::Count(window) => (0..n.min(window.size)).for_each(|_| window.record(outcome)),
+            Window::Time(window) => window.record(outcome, n, now),
+        }
+    }
+
+    pub(crate) fn stats(&self, now: Duration) -> WindowStats {
+        match self {
+            Window::Count(window) => window.stats,
+            Window::Time(window) => window.stats(now),
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        match self {
+            Window::Count(window) => window.clear(),
+            Window::Time(window) => window.clear(),
+        }
+    }
+
+    /// Return the buffered outcomes, oldest first. A time-based window only
+    /// keeps aggregates, so it has none.
+    pub(crate) fn outcomes(&self) -> Vec<Outcome> {
+        match self {
+            Window::Count(window) => window.outcomes.iter().copied().collect(),
+            Window::Time(_) => Vec::new(),
+        }
+    }
+
+    /// Return the start and the aggregated outcomes of the unexpired buckets
+    /// holding any, oldest first. A count-based window has no buckets.
+    pub(crate) fn buckets(&self, now: Duration) -> Vec<(Duration, WindowStats)> {
+        match self {
+            Window::Count(_) => Vec::new(),
+            Window::Time(window) => window.buckets(now),
+        }
+    }
+}
+
+/// A count-based sliding window keeping the outcomes of the last `size` calls.
+#[derive(Debug)]
+pub(crate) struct CountWindow {
+    size: usize,
+    // The oldest outcome first.
+    outcomes: VecDeque<Outcome>,
+    stats: WindowStats,
+}
+
+impl CountWindow {
+    pub(crate) fn new(size: usize) -> Self {
+        CountWindow {
+            size,
+            outcomes: VecDeque::with_capacity(size),
+            stats: WindowStats::default(),
+        }
+    }
+
+    /// Record an outcome, evicting the oldest one once the window is full.
+    pub(crate) fn record(&mut self, outcome: Outcome) {
+        if self.outcomes.len() == self.size {
+            if let Some(evicted) = self.outcomes.pop_front() {
+                self.stats.remove(evicted);
+            }
+        }
+        if self.size > 0 {
+            self.outcomes.push_back(outcome);
+            self.stats.add(outcome, 1);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.outcomes.clear();
+        self.stats = WindowStats::default();
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Bucket {
+    // The index of the interval since the window's origin this bucket counts.
+    epoch: u64,
+    stats: WindowStats,
+}
+
+/// A time-based sliding window splitting its `duration` into buckets which
+/// expire as time advances.
+#[derive(Debug)]
+pub(crate) struct TimeWindow {
+    origin: Duration,
+    bucket_len: Duration,
+    buckets: Vec<Bucket>,
+}
+
+impl TimeWindow {
+    pub(crate) fn new(duration: Duration, now: Duration) -> Self {
+        TimeWindow {
+            origin: now,
+            bucket_len: (duration / TIME_WINDOW_BUCKETS).max(Duration::from_nanos(1)),
+            buckets: vec![Bucket::default(); TIME_WINDOW_BUCKETS as usize],
+        }
+    }
+
+    fn epoch(&self, now: Duration) -> u64 {
+        let elapsed = now.saturating_sub(self.origin);
+        (elapsed.as_nanos() / self.bucket_len.as_nanos()) as u64
+    }
+
+    /// Record `n` calls with the same outcome into the bucket of the current
+    /// interval.
+    pub(crate) fn record(&mut self, outcome: Outcome, n: usize, now: Duration) {
+        let epoch = self.epoch(now);
+        let bucket = &mut self.buckets[(epoch % TIME_WINDOW_BUCKETS as u64) as usize];
+        if bucket.epoch != epoch {
+            *bucket = Bucket {
+                epoch,
+                ..Bucket::default()
+            };
+        }
+        bucket.stats.add(outcome, n);
+    }
+
+    fn unexpired(&self, now: Duration) -> impl Iterator<Item = &Bucket> {
+        let epoch = self.epoch(now);
+        self.buckets
+            .iter()
+            .filter(move |bucket| epoch.saturating_sub(bucket.epoch) < TIME_WINDOW_BUCKETS as u64)
+    }
+
+    /// Return the aggregated outcomes of the unexpired buckets.
+    pub(crate) fn stats(&self, now: Duration) -> WindowStats {
+        self.unexpired(now)
+            .fold(WindowStats::default(), |stats, bucket| {
+                stats.merge(bucket.stats)
+            })
+    }
+
+    /// Return the start and the aggregated outcomes of the unexpired buckets
+    /// holding any, oldest first.
+    pub(crate) fn buckets(&self, now: Duration) -> Vec<(Duration, WindowStats)> {
+        let mut buckets: Vec<_> = self
+            .unexpired(now)
+            .filter(|bucket| bucket.stats.calls > 0)
+            .collect();
+        buckets.sort_by_key(|bucket| bucket.epoch);
+        buckets
+            .into_iter()
+            .map(|bucket| {
+                let offset = self.bucket_len.as_nanos() * u128::from(bucket.epoch);
+                let offset = Duration::from_nanos(u64::try_from(offset).unwrap_or(u64::MAX));
+                (self.origin.saturating_add(offset), bucket.stats)
+            })
+            .collect()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.buckets.fill(Bucket::default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(failure: bool) -> Outcome {
+        Outcome {
+            at: Duration::ZERO,
+            failure,
+            slow: false,
+        }
+    }
+
+    #[test]
+    fn failure_rate_covers_the_last_calls_only() {
+        let mut window = CountWindow::new(4);
+        for failure in [true, false, false, false] {
+            window.record(outcome(failure));
+        }
+        assert_eq!(window.stats.failure_rate(), 0.25);
+
+        // The first failure slides out of the window.
+        window.record(outcome(false));
+        assert_eq!(window.stats.failure_rate(), 0.0);
+        window.record(outcome(true));
+        window.record(outcome(true));
+        assert_eq!(window.stats.failure_rate(), 0.5);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn time_window_forgets_expired_failures_and_trips_on_recent_ones() {
+        use std::time::Duration;
+
+        use crate::circuit_breaker::CircuitBreaker;
+        use crate::clock::TestClock;
+        use crate::state::State;
+        use crate::state_machine::StateMachine;
+
+        let clock = TestClock::new();
+        let breaker = StateMachine::builder()
+            .window_duration(Duration::from_secs(10))
+            .failure_rate_threshold(0.5)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let succeed = |n| (0..n).for_each(|_| drop(breaker.call(|| Ok::<_, ()>(()))));
+        let fail = |n| (0..n).for_each(|_| drop(breaker.call(|| Err::<(), _>(()))));
+
+        succeed(10);
+        clock.advance(Duration::from_secs(5));
+        fail(5);
+        clock.advance(Duration::from_secs(6));
+        succeed(5);
+        assert_eq!(breaker.current_state(), State::Closed);
+
+        // The five failures expired; counting them would make it 6 out of 11.
+        clock.advance(Duration::from_secs(5));
+        fail(4);
+        assert_eq!(breaker.current_state(), State::Closed);
+        fail(1);
+        assert!(matches!(breaker.current_state(), State::Open { .. }));
+    }
+}
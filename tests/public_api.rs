@@ -0,0 +1,25 @@
+//! Uses the circuit breaker the way a downstream crate does, through its
+//! public API only.
+#![cfg(feature = "std")]
+
+use std::time::Duration;
+
+use circuit_breaker::{CircuitBreaker, Error, StateMachine};
+
+#[test]
+fn trips_and_rejects_through_the_public_api() {
+    let breaker = StateMachine::new(2, Duration::from_secs(60));
+    assert!(breaker.is_call_permitted());
+    assert!(matches!(breaker.call(|| Ok::<_, ()>(1)), Ok(1)));
+
+    for _ in 0..2 {
+        assert!(matches!(
+            breaker.call(|| Err::<(), _>("refused")),
+            Err(Error::Inner("refused"))
+        ));
+    }
+    assert!(matches!(
+        breaker.call(|| Ok::<_, ()>(1)),
+        Err(Error::Rejected { .. })
+    ));
+}